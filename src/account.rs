@@ -2,12 +2,130 @@
 
 use serde::{Deserialize, Serialize};
 
-/// `Account` represents an account that can be committed to the database.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::encoding::Encoding;
+use crate::error::PPHResult;
+use crate::hasher::KdfParams;
+
+/// `ShareEntry` is a single protected hash held by an account, along with the share number of the
+/// master secret that protects it. Thresholdless accounts hold a single entry with share number 0.
+/// With Pedersen commitments, threshold entries also hold their protected blinding share.
+#[derive(Debug, Clone)]
+pub struct ShareEntry {
+    pub sharenumber: u16,
+    pub passhash: Vec<u8>,
+    pub blinding: Option<Vec<u8>>,
+}
+
+/// `Account` represents a user record that can be committed to the database, owning every entry
+/// held by the user under a stable unique ID, along with the algorithm and parameters its salted
+/// password hash was computed with and the ID of the pepper mixed into it, if any. Accounts
+/// migrated from a legacy password hash also record its setting until they are upgraded, and
+/// accounts whose entries were re-protected by a share refresh are marked until they are
+/// re-salted.
+#[derive(Debug, Clone)]
 pub struct Account {
-    pub id: i64,
+    pub id: u64,
     pub username: String,
-    pub salt: String,
-    pub sharenumber: u8,
+    pub salt: Vec<u8>,
+    pub kdf: KdfParams,
+    pub pepper: Option<String>,
+    pub legacy: Option<String>,
+    pub refreshed: bool,
+    pub entries: Vec<ShareEntry>,
+}
+
+impl Account {
+    /// checks if the account is a thresholdless account, meaning that its hash is protected by a
+    /// key derived from the master secret rather than a share.
+    #[inline]
+    pub fn is_thresholdless(&self) -> bool {
+        self.entries.iter().all(|entry| entry.sharenumber == 0)
+    }
+
+    /// returns the share numbers of the master secret held by the account.
+    #[inline]
+    pub fn sharenumbers(&self) -> Vec<u16> {
+        self.entries
+            .iter()
+            .map(|entry| entry.sharenumber)
+            .filter(|sharenumber| *sharenumber != 0)
+            .collect()
+    }
+
+    /// encodes the binary fields of the account for storage.
+    pub fn encode(&self, encoding: Encoding) -> EncodedAccount {
+        EncodedAccount {
+            id: self.id,
+            username: self.username.clone(),
+            salt: encoding.encode(&self.salt),
+            kdf: self.kdf.clone(),
+            pepper: self.pepper.clone(),
+            legacy: self.legacy.clone(),
+            refreshed: self.refreshed,
+            entries: self.entries
+                .iter()
+                .map(|entry| EncodedShareEntry {
+                    sharenumber: entry.sharenumber,
+                    passhash: encoding.encode(&entry.passhash),
+                    blinding: entry.blinding.as_ref().map(|blinding| encoding.encode(blinding)),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// `EncodedShareEntry` is the representation of a `ShareEntry` written to the password file, with
+/// its protected hash and blinding share encoded as text.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncodedShareEntry {
+    pub sharenumber: u16,
     pub passhash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blinding: Option<String>,
+}
+
+/// `EncodedAccount` is the representation of an `Account` written to the password file, with its
+/// salt and protected hashes encoded as text.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncodedAccount {
+    pub id: u64,
+    pub username: String,
+    pub salt: String,
+    pub kdf: KdfParams,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pepper: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub legacy: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub refreshed: bool,
+    pub entries: Vec<EncodedShareEntry>,
+}
+
+impl EncodedAccount {
+    /// decodes the binary fields of a stored account.
+    pub fn decode(self, encoding: Encoding) -> PPHResult<Account> {
+        let mut entries: Vec<ShareEntry> = vec![];
+        for entry in self.entries {
+            let blinding = match entry.blinding {
+                Some(blinding) => Some(encoding.decode(&blinding)?),
+                None => None,
+            };
+            entries.push(ShareEntry {
+                sharenumber: entry.sharenumber,
+                passhash: encoding.decode(&entry.passhash)?,
+                blinding,
+            });
+        }
+
+        Ok(Account {
+            id: self.id,
+            username: self.username,
+            salt: encoding.decode(&self.salt)?,
+            kdf: self.kdf,
+            pepper: self.pepper,
+            legacy: self.legacy,
+            refreshed: self.refreshed,
+            entries,
+        })
+    }
 }
@@ -0,0 +1,321 @@
+//! Defines the builder used to configure a PolyPasswordHasher database, either created afresh or
+//! loaded from a password file.
+
+use crate::database::{Header, PasswordFile};
+use crate::encoding::Encoding;
+use crate::error::{PPHError, PPHErrorKind, PPHResult};
+use crate::hasher::{Argon2idHasher, PasswordHasher, DEFAULT_DIGEST_SIZE, DIGEST_SIZES};
+use crate::math::{Field, FieldKind};
+use crate::pepper::Pepper;
+use crate::secretshare::ShamirSecret;
+use crate::vss::VssMode;
+use crate::{Accounts, PolyPasswordHasher};
+
+use std::collections::HashMap;
+use std::fs;
+use std::marker::PhantomData;
+
+/// size of the salt generated for each account unless another one is configured.
+pub const DEFAULT_SALT_SIZE: u8 = 16;
+
+/// bounds of the salt size, in bytes.
+pub const MIN_SALT_SIZE: u8 = 8;
+pub const MAX_SALT_SIZE: u8 = 64;
+
+/// returns a configuration error for an invalid combination of settings.
+fn config_error(msg: String) -> PPHError {
+    PPHError {
+        kind: PPHErrorKind::ConfigError,
+        msg,
+    }
+}
+
+/// `PolyPasswordHasherBuilder` collects the settings of a database and checks that they can be
+/// used together before producing it. Settings left unset take their defaults for a new database,
+/// or are read from the header of a loaded one, in which case the settings given must match it.
+/// The threshold given is only used for new databases, as loaded ones take it from the header.
+/// Shares are computed over GF(2^8) unless another field is selected with `field()`.
+pub struct PolyPasswordHasherBuilder<F: Field = u8> {
+    threshold: u8,
+    passwordfile: Option<String>,
+    saltsize: Option<u8>,
+    partialbytes: Option<u8>,
+    digestsize: Option<u8>,
+    hasher: Option<Box<dyn PasswordHasher>>,
+    peppers: Vec<Pepper>,
+    encoding: Option<Encoding>,
+    vss: Option<VssMode>,
+    field: PhantomData<F>,
+}
+
+impl PolyPasswordHasherBuilder {
+    /// starts configuring a database needing the given threshold number of shares to unlock. A
+    /// database loaded from a password file keeps the threshold recorded in it instead.
+    pub fn new(threshold: u8) -> Self {
+        PolyPasswordHasherBuilder {
+            threshold,
+            passwordfile: None,
+            saltsize: None,
+            partialbytes: None,
+            digestsize: None,
+            hasher: None,
+            peppers: vec![],
+            encoding: None,
+            vss: None,
+            field: PhantomData,
+        }
+    }
+}
+
+impl<F: Field> PolyPasswordHasherBuilder<F> {
+    /// selects the field shares are computed over, such as `u16` for GF(2^16) to hand out more
+    /// than the 255 share numbers available in GF(2^8). The field is recorded in the password file
+    /// and must be selected again when it is loaded.
+    pub fn field<G: Field>(self) -> PolyPasswordHasherBuilder<G> {
+        PolyPasswordHasherBuilder {
+            threshold: self.threshold,
+            passwordfile: self.passwordfile,
+            saltsize: self.saltsize,
+            partialbytes: self.partialbytes,
+            digestsize: self.digestsize,
+            hasher: self.hasher,
+            peppers: self.peppers,
+            encoding: self.encoding,
+            vss: self.vss,
+            field: PhantomData,
+        }
+    }
+
+    /// loads the database from an existing password file instead of creating a new one.
+    pub fn passwordfile(mut self, passwordfile: String) -> Self {
+        self.passwordfile = Some(passwordfile);
+        self
+    }
+
+    /// sets the size of the salt generated for each account, defaulting to `DEFAULT_SALT_SIZE`.
+    pub fn saltsize(mut self, saltsize: u8) -> Self {
+        self.saltsize = Some(saltsize);
+        self
+    }
+
+    /// sets the number of bytes of each salted hash to leak for partial verification, defaulting
+    /// to none.
+    pub fn partialbytes(mut self, partialbytes: u8) -> Self {
+        self.partialbytes = Some(partialbytes);
+        self
+    }
+
+    /// sets the length of the salted password hashes and of the master secret, which must be one
+    /// of `DIGEST_SIZES` and defaults to `DEFAULT_DIGEST_SIZE`.
+    pub fn digestsize(mut self, digestsize: u8) -> Self {
+        self.digestsize = Some(digestsize);
+        self
+    }
+
+    /// sets the hasher used for new and re-hashed accounts, defaulting to Argon2id, or to the
+    /// hasher recorded in the password file when it is loaded.
+    pub fn hasher(mut self, hasher: Box<dyn PasswordHasher>) -> Self {
+        self.hasher = Some(hasher);
+        self
+    }
+
+    /// adds a pepper. The last pepper added is the current one.
+    pub fn pepper(mut self, pepper: Pepper) -> Self {
+        self.peppers.push(pepper);
+        self
+    }
+
+    /// sets the encoding used for salts and protected hashes when the database is committed.
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    /// enables verifiable secret sharing, publishing commitments to the master secret in the
+    /// password file so that shares can be checked before unlocking. It requires the Ristretto255
+    /// field, selected with `field::<Scalar>()`, and a password file with commitments is always
+    /// verified when it is loaded. Feldman commitments reveal the master secret to anyone able to
+    /// compute discrete logarithms, while Pedersen commitments hide it unconditionally. With
+    /// either mode, as with partial bytes, the commitments let anyone holding the password file
+    /// check guesses of the password of a single threshold account.
+    pub fn vss(mut self, mode: VssMode) -> Self {
+        self.vss = Some(mode);
+        self
+    }
+
+    /// checks that the settings of a database can be used together.
+    pub(crate) fn validate(
+        threshold: u8,
+        saltsize: u8,
+        partialbytes: u8,
+        digestsize: u8,
+    ) -> PPHResult<()> {
+        if threshold < 1 {
+            return Err(config_error("threshold must be at least 1".to_string()));
+        }
+        if !(MIN_SALT_SIZE..=MAX_SALT_SIZE).contains(&saltsize) {
+            return Err(config_error(format!(
+                "salt size {} is outside of {} to {}",
+                saltsize, MIN_SALT_SIZE, MAX_SALT_SIZE
+            )));
+        }
+        if !DIGEST_SIZES.contains(&(digestsize as usize)) {
+            return Err(config_error(format!("unsupported digest size {}", digestsize)));
+        }
+        if !(digestsize as usize).is_multiple_of(F::BYTES) {
+            return Err(config_error(format!(
+                "digest size {} is not a whole number of field elements",
+                digestsize
+            )));
+        }
+        if partialbytes >= digestsize {
+            return Err(config_error(format!(
+                "{} partial bytes would leak the whole {}-byte digest",
+                partialbytes, digestsize
+            )));
+        }
+        Ok(())
+    }
+
+    /// produces the configured database, either new with a fresh master secret, or loaded from
+    /// its password file and locked until enough shares are supplied.
+    pub fn build(self) -> PPHResult<PolyPasswordHasher<F>> {
+        let mut pph = match self.passwordfile {
+            Some(ref passwordfile) => self.load(passwordfile)?,
+            None => self.create()?,
+        };
+
+        if let Some(encoding) = self.encoding {
+            pph.encoding = encoding;
+        }
+        if let Some(hasher) = self.hasher {
+            pph.hasher = hasher;
+        }
+        for pepper in self.peppers {
+            pph.add_pepper(pepper);
+        }
+        Ok(pph)
+    }
+
+    /// creates an empty database with a new master secret, indicating a first-time setup.
+    fn create(&self) -> PPHResult<PolyPasswordHasher<F>> {
+        let saltsize = self.saltsize.unwrap_or(DEFAULT_SALT_SIZE);
+        let partialbytes = self.partialbytes.unwrap_or(0);
+        let digestsize = self.digestsize.unwrap_or(DEFAULT_DIGEST_SIZE as u8);
+        Self::validate(self.threshold, saltsize, partialbytes, digestsize)?;
+        if self.vss.is_some() && F::KIND != FieldKind::Ristretto255 {
+            return Err(config_error(
+                "verifiable secret sharing requires the Ristretto255 field".to_string(),
+            ));
+        }
+
+        let mut pph = PolyPasswordHasher {
+            threshold: self.threshold,
+            accounts: Accounts::new(),
+            shamirsecretobj: None,
+            knownsecret: true,
+            secretcheck: vec![],
+            thresholdlesskey: None,
+            saltsize,
+            partialbytes,
+            digestsize,
+            hasher: Box::new(Argon2idHasher::default()),
+            peppers: HashMap::new(),
+            currentpepper: None,
+            encoding: Encoding::default(),
+            nextavailableshare: 1,
+            nextaccountid: 0,
+            retiredshares: vec![],
+            tentativelogins: HashMap::new(),
+            vss: self.vss,
+            commitments: None,
+            blindingsecretobj: None,
+        };
+        pph.generate_secret()?;
+        Ok(pph)
+    }
+
+    /// loads a locked database from a password file, checking its header against the settings.
+    fn load(&self, passwordfile: &str) -> PPHResult<PolyPasswordHasher<F>> {
+        let raw_content = fs::read_to_string(passwordfile)?;
+        let passwordfile: PasswordFile = serde_json::from_str::<PasswordFile>(&raw_content)?;
+        let header: Header = passwordfile.header;
+        header.validate(
+            self.saltsize,
+            self.partialbytes,
+            self.digestsize,
+            F::KIND,
+        )?;
+        Self::validate(
+            header.threshold,
+            header.saltsize,
+            header.partialbytes,
+            header.digestsize,
+        )?;
+
+        let commitments = match &header.commitments {
+            Some(commitments) => Some(commitments.decode(header.encoding)?),
+            None => None,
+        };
+        let vss = commitments.as_ref().map(|commitments| commitments.mode);
+        if self.vss.is_some() && self.vss != vss {
+            return Err(PPHError {
+                kind: PPHErrorKind::FormatError,
+                msg: "password file was not written with the requested verifiable secret sharing"
+                    .to_string(),
+            });
+        }
+
+        // decode the accounts with the encoding recorded in the header, checking that every
+        // account holds either a single thresholdless entry or only threshold entries, and that
+        // every protected hash and blinding share has the length given by the header
+        let passhashlength = header.digestsize as usize + header.partialbytes as usize;
+        let mut accounts: Accounts = Accounts::new();
+        for (username, account) in passwordfile.accounts {
+            let account = account.decode(header.encoding)?;
+            let thresholdless = account.entries.iter().any(|entry| entry.sharenumber == 0);
+            if account.entries.is_empty() || (thresholdless && account.entries.len() > 1) {
+                return Err(PPHError {
+                    kind: PPHErrorKind::FormatError,
+                    msg: format!("entries of {} are neither thresholdless nor shares", username),
+                });
+            }
+            let malformed = account.entries.iter().any(|entry| {
+                entry.passhash.len() != passhashlength
+                    || entry.blinding.as_ref().is_some_and(|blinding| {
+                        blinding.len() != header.digestsize as usize
+                    })
+            });
+            if malformed {
+                return Err(PPHError {
+                    kind: PPHErrorKind::FormatError,
+                    msg: format!("protected hash length of {} does not match the header", username),
+                });
+            }
+            accounts.insert(username, account);
+        }
+
+        Ok(PolyPasswordHasher {
+            threshold: header.threshold,
+            accounts,
+            shamirsecretobj: Some(ShamirSecret::with_field(header.threshold, None)?),
+            knownsecret: false,
+            secretcheck: header.encoding.decode(&header.secretcheck)?,
+            thresholdlesskey: None,
+            saltsize: header.saltsize,
+            partialbytes: header.partialbytes,
+            digestsize: header.digestsize,
+            hasher: header.kdf.hasher(),
+            peppers: HashMap::new(),
+            currentpepper: None,
+            encoding: header.encoding,
+            nextavailableshare: header.nextavailableshare,
+            nextaccountid: header.nextaccountid,
+            retiredshares: header.retiredshares,
+            tentativelogins: HashMap::new(),
+            vss,
+            commitments,
+            blindingsecretobj: None,
+        })
+    }
+}
@@ -0,0 +1,102 @@
+//! Defines the versioned format of the password file written by PolyPasswordHasher, made up of a
+//! header recording the settings of the database and its encoded accounts.
+
+use serde::{Deserialize, Serialize};
+
+use crate::account::EncodedAccount;
+use crate::encoding::Encoding;
+use crate::error::{PPHError, PPHErrorKind, PPHResult};
+use crate::hasher::{KdfParams, DEFAULT_DIGEST_SIZE};
+use crate::math::FieldKind;
+use crate::vss::EncodedCommitments;
+
+use std::collections::HashMap;
+
+/// current version of the password file format, bumped on incompatible changes.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// `Header` records the settings a database was created with, so that they do not need to be
+/// re-supplied when it is loaded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Header {
+    pub version: u32,
+    pub threshold: u8,
+    pub saltsize: u8,
+    pub partialbytes: u8,
+    #[serde(default = "default_digest_size")]
+    pub digestsize: u8,
+    #[serde(default)]
+    pub field: FieldKind,
+    pub kdf: KdfParams,
+    pub encoding: Encoding,
+    pub nextavailableshare: u32,
+    pub nextaccountid: u64,
+    pub retiredshares: Vec<u16>,
+    pub secretcheck: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commitments: Option<EncodedCommitments>,
+}
+
+/// digest size of password files written before it was recorded in the header.
+fn default_digest_size() -> u8 {
+    DEFAULT_DIGEST_SIZE as u8
+}
+
+impl Header {
+    /// checks that the header was written in a supported format version and with settings that
+    /// match the ones requested for the database. The threshold is not checked, as it can be
+    /// changed on an unlocked database and is always read from the header.
+    pub fn validate(
+        &self,
+        saltsize: Option<u8>,
+        partialbytes: Option<u8>,
+        digestsize: Option<u8>,
+        field: FieldKind,
+    ) -> PPHResult<()> {
+        if self.version != FORMAT_VERSION {
+            return Err(PPHError {
+                kind: PPHErrorKind::FormatError,
+                msg: format!("unsupported password file version {}", self.version),
+            });
+        }
+
+        let settings = [
+            ("salt size", self.saltsize, saltsize),
+            ("partial bytes", self.partialbytes, partialbytes),
+            ("digest size", self.digestsize, digestsize),
+        ];
+        for (name, value, requested) in settings {
+            match requested {
+                Some(requested) if requested != value => {
+                    return Err(PPHError {
+                        kind: PPHErrorKind::FormatError,
+                        msg: format!(
+                            "password file has {} {}, but {} was requested",
+                            name, value, requested
+                        ),
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        if self.field != field {
+            return Err(PPHError {
+                kind: PPHErrorKind::FormatError,
+                msg: format!(
+                    "password file uses field {:?}, but {:?} was requested",
+                    self.field, field
+                ),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// `PasswordFile` is the contents of a password file, with the binary fields of its accounts
+/// encoded as recorded in the header.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasswordFile {
+    pub header: Header,
+    pub accounts: HashMap<String, EncodedAccount>,
+}
@@ -0,0 +1,33 @@
+//! Defines the text encodings used to store binary fields in the password file.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::PPHResult;
+
+/// `Encoding` selects how salts and protected hashes are written to the password file. The
+/// encoding used is recorded in the file, so either can be read back.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    #[default]
+    Base64,
+    Hex,
+}
+
+impl Encoding {
+    /// encodes a buffer of bytes as text.
+    pub fn encode(&self, data: &[u8]) -> String {
+        match self {
+            Encoding::Base64 => base64::encode(data),
+            Encoding::Hex => hex::encode(data),
+        }
+    }
+
+    /// decodes text back into a buffer of bytes.
+    pub fn decode(&self, data: &str) -> PPHResult<Vec<u8>> {
+        match self {
+            Encoding::Base64 => Ok(base64::decode(data)?),
+            Encoding::Hex => Ok(hex::decode(data)?),
+        }
+    }
+}
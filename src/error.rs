@@ -12,7 +12,14 @@ pub enum PPHErrorKind {
     ShardError,
     AuthError,
     FileError,
-    SerError
+    SerError,
+    FormatError,
+    RecoveryError,
+    ShareError,
+    FieldError,
+    HashError,
+    PepperError,
+    ConfigError
 }
 
 /// the main error struct that encapsulates an error kind and a message
@@ -22,6 +29,14 @@ pub struct PPHError {
     pub msg: String
 }
 
+/// returns a format error for malformed input, such as a password string or a legacy hash.
+pub(crate) fn format_error(msg: String) -> PPHError {
+    PPHError {
+        kind: PPHErrorKind::FormatError,
+        msg
+    }
+}
+
 impl fmt::Display for PPHError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "PPHError with error kind {:?}: {}", self.kind, self.msg)
@@ -46,4 +61,22 @@ impl From<serde_json::Error> for PPHError {
     }
 }
 
+impl From<base64::DecodeError> for PPHError {
+    fn from(error: base64::DecodeError) -> Self {
+        PPHError {
+            kind: PPHErrorKind::SerError,
+            msg: error.to_string()
+        }
+    }
+}
+
+impl From<hex::FromHexError> for PPHError {
+    fn from(error: hex::FromHexError) -> Self {
+        PPHError {
+            kind: PPHErrorKind::SerError,
+            msg: error.to_string()
+        }
+    }
+}
+
 impl Error for PPHError {}
@@ -0,0 +1,357 @@
+//! Defines the password hashing step that computes the salted hash protected by each account, and
+//! the algorithms that can be used for it.
+
+use libsodium_sys as ffi;
+use serde::{Deserialize, Serialize};
+use sodiumoxide::crypto::auth::{hmacsha256, hmacsha512};
+use sodiumoxide::crypto::generichash;
+use sodiumoxide::crypto::hash::sha256;
+use sodiumoxide::crypto::pwhash::{argon2id13, scryptsalsa208sha256};
+
+use crate::error::{PPHError, PPHErrorKind, PPHResult};
+
+use std::mem;
+
+/// lengths of salted password hash that every hasher can compute. The master secret, and so the
+/// share data protecting each hash, has the same length.
+pub const DIGEST_SIZES: [usize; 3] = [32, 48, 64];
+
+/// length of the salted password hash used unless another one is configured.
+pub const DEFAULT_DIGEST_SIZE: usize = sha256::DIGESTBYTES;
+
+/// `KdfParams` identifies the algorithm used to compute salted password hashes, along with its
+/// parameters, as recorded in the password file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "algorithm", rename_all = "lowercase")]
+pub enum KdfParams {
+    Sha256,
+    Argon2id { opslimit: u64, memlimit: u64 },
+    Scrypt { opslimit: u64, memlimit: u64 },
+    Pbkdf2 { iterations: u32 },
+    Bcrypt { cost: u32 },
+}
+
+impl KdfParams {
+    /// constructs the hasher described by the parameters.
+    pub fn hasher(&self) -> Box<dyn PasswordHasher> {
+        match *self {
+            KdfParams::Sha256 => Box::new(Sha256Hasher),
+            KdfParams::Argon2id { opslimit, memlimit } => {
+                Box::new(Argon2idHasher { opslimit, memlimit })
+            }
+            KdfParams::Scrypt { opslimit, memlimit } => {
+                Box::new(ScryptHasher { opslimit, memlimit })
+            }
+            KdfParams::Pbkdf2 { iterations } => Box::new(Pbkdf2Hasher { iterations }),
+            KdfParams::Bcrypt { cost } => Box::new(BcryptHasher { cost }),
+        }
+    }
+}
+
+/// fits a salt of any size to the fixed salt size taken by a password hashing function, hashing it
+/// if its size differs.
+fn fit_salt(salt: &[u8], saltbytes: usize) -> Vec<u8> {
+    if salt.len() == saltbytes {
+        return salt.to_vec();
+    }
+    let mut state = generichash::State::new(Some(saltbytes), None).unwrap();
+    state.update(salt).unwrap();
+    state.finalize().unwrap().as_ref().to_vec()
+}
+
+/// checks that a salted password hash length is one of the supported digest sizes.
+fn check_length(length: usize) -> PPHResult<()> {
+    if !DIGEST_SIZES.contains(&length) {
+        return Err(PPHError {
+            kind: PPHErrorKind::HashError,
+            msg: format!("unsupported digest size {}", length),
+        });
+    }
+    Ok(())
+}
+
+/// expands the output of a hash function to the given length with BLAKE2b.
+fn expand(output: &[u8], length: usize) -> Vec<u8> {
+    let mut state = generichash::State::new(Some(length), None).unwrap();
+    state.update(output).unwrap();
+    state.finalize().unwrap().as_ref().to_vec()
+}
+
+/// computes HMAC-SHA256 of a message, or HMAC-SHA512 for outputs longer than its 32 bytes,
+/// truncated to the given length of at most 64 bytes.
+pub(crate) fn hmac(key: &[u8], message: &[u8], length: usize) -> Vec<u8> {
+    let mut tag: Vec<u8> = if length <= hmacsha256::TAGBYTES {
+        let mut state = hmacsha256::State::init(key);
+        state.update(message);
+        state.finalize().as_ref().to_vec()
+    } else {
+        let mut state = hmacsha512::State::init(key);
+        state.update(message);
+        state.finalize().as_ref().to_vec()
+    };
+    tag.truncate(length);
+    tag
+}
+
+/// `PasswordHasher` computes the salted hash of a password that is protected with a share of the
+/// master secret, as long as the secret.
+pub trait PasswordHasher {
+    /// returns the parameters recorded in the password file to reconstruct the hasher.
+    fn params(&self) -> KdfParams;
+
+    /// hashes a password with the salt of its account into a hash of the given length, which must
+    /// be one of `DIGEST_SIZES`.
+    fn derive(&self, salt: &[u8], password: &str, length: usize) -> PPHResult<Vec<u8>>;
+
+    /// hashes a password with the salt of its account into a hash of the default length.
+    fn hash(&self, salt: &[u8], password: &str) -> PPHResult<Vec<u8>> {
+        self.derive(salt, password, DEFAULT_DIGEST_SIZE)
+    }
+}
+
+/// `Sha256Hasher` hashes the salt and the password with a single round of SHA-256, expanded with
+/// BLAKE2b for longer hashes. It is far too fast to resist offline guessing on its own, and is only
+/// kept for existing databases.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sha256Hasher;
+
+impl PasswordHasher for Sha256Hasher {
+    fn params(&self) -> KdfParams {
+        KdfParams::Sha256
+    }
+
+    fn derive(&self, salt: &[u8], password: &str, length: usize) -> PPHResult<Vec<u8>> {
+        check_length(length)?;
+        let mut saltpass: Vec<u8> = salt.to_vec();
+        saltpass.extend_from_slice(password.as_bytes());
+        let sha256::Digest(saltedpasswordhash) = sha256::hash(&saltpass);
+        if length == sha256::DIGESTBYTES {
+            return Ok(saltedpasswordhash.to_vec());
+        }
+        Ok(expand(&saltedpasswordhash, length))
+    }
+}
+
+/// `Argon2idHasher` hashes passwords with the memory-hard Argon2id function, and is the default
+/// hasher for new databases.
+#[derive(Debug, Clone, Copy)]
+pub struct Argon2idHasher {
+    pub opslimit: u64,
+    pub memlimit: u64,
+}
+
+impl Default for Argon2idHasher {
+    fn default() -> Self {
+        let argon2id13::OpsLimit(opslimit) = argon2id13::OPSLIMIT_INTERACTIVE;
+        let argon2id13::MemLimit(memlimit) = argon2id13::MEMLIMIT_INTERACTIVE;
+        Argon2idHasher {
+            opslimit: opslimit as u64,
+            memlimit: memlimit as u64,
+        }
+    }
+}
+
+impl PasswordHasher for Argon2idHasher {
+    fn params(&self) -> KdfParams {
+        KdfParams::Argon2id {
+            opslimit: self.opslimit,
+            memlimit: self.memlimit,
+        }
+    }
+
+    fn derive(&self, salt: &[u8], password: &str, length: usize) -> PPHResult<Vec<u8>> {
+        check_length(length)?;
+        let salt = argon2id13::Salt::from_slice(&fit_salt(salt, argon2id13::SALTBYTES)).unwrap();
+
+        let mut saltedpasswordhash = vec![0u8; length];
+        argon2id13::derive_key(
+            &mut saltedpasswordhash,
+            password.as_bytes(),
+            &salt,
+            argon2id13::OpsLimit(self.opslimit as usize),
+            argon2id13::MemLimit(self.memlimit as usize),
+        )
+        .map_err(|_| PPHError {
+            kind: PPHErrorKind::HashError,
+            msg: "argon2id could not hash the password with the given limits".to_string(),
+        })?;
+        Ok(saltedpasswordhash)
+    }
+}
+
+/// `ScryptHasher` hashes passwords with scrypt, for parity with systems that already use it.
+#[derive(Debug, Clone, Copy)]
+pub struct ScryptHasher {
+    pub opslimit: u64,
+    pub memlimit: u64,
+}
+
+impl Default for ScryptHasher {
+    fn default() -> Self {
+        let scryptsalsa208sha256::OpsLimit(opslimit) = scryptsalsa208sha256::OPSLIMIT_INTERACTIVE;
+        let scryptsalsa208sha256::MemLimit(memlimit) = scryptsalsa208sha256::MEMLIMIT_INTERACTIVE;
+        ScryptHasher {
+            opslimit: opslimit as u64,
+            memlimit: memlimit as u64,
+        }
+    }
+}
+
+impl PasswordHasher for ScryptHasher {
+    fn params(&self) -> KdfParams {
+        KdfParams::Scrypt {
+            opslimit: self.opslimit,
+            memlimit: self.memlimit,
+        }
+    }
+
+    fn derive(&self, salt: &[u8], password: &str, length: usize) -> PPHResult<Vec<u8>> {
+        check_length(length)?;
+        let salt = fit_salt(salt, scryptsalsa208sha256::SALTBYTES);
+        let salt = scryptsalsa208sha256::Salt::from_slice(&salt).unwrap();
+
+        let mut saltedpasswordhash = vec![0u8; length];
+        scryptsalsa208sha256::derive_key(
+            &mut saltedpasswordhash,
+            password.as_bytes(),
+            &salt,
+            scryptsalsa208sha256::OpsLimit(self.opslimit as usize),
+            scryptsalsa208sha256::MemLimit(self.memlimit as usize),
+        )
+        .map_err(|_| PPHError {
+            kind: PPHErrorKind::HashError,
+            msg: "scrypt could not hash the password with the given limits".to_string(),
+        })?;
+        Ok(saltedpasswordhash)
+    }
+}
+
+/// `Pbkdf2Hasher` hashes passwords with PBKDF2-HMAC-SHA256, for deployments restricted to
+/// FIPS-approved primitives. The iteration count is recorded with each account, so that it can be
+/// raised for new passwords without invalidating existing ones.
+#[derive(Debug, Clone, Copy)]
+pub struct Pbkdf2Hasher {
+    pub iterations: u32,
+}
+
+impl Default for Pbkdf2Hasher {
+    fn default() -> Self {
+        Pbkdf2Hasher { iterations: 600_000 }
+    }
+}
+
+/// `KeyedHmacSha256` is an HMAC-SHA256 state keyed once, which is copied to authenticate every
+/// message rather than hashing the key again each time. Copies are zeroed once used.
+struct KeyedHmacSha256(ffi::crypto_auth_hmacsha256_state);
+
+impl KeyedHmacSha256 {
+    fn new(key: &[u8]) -> KeyedHmacSha256 {
+        let mut state = mem::MaybeUninit::uninit();
+        unsafe {
+            ffi::crypto_auth_hmacsha256_init(state.as_mut_ptr(), key.as_ptr(), key.len());
+            KeyedHmacSha256(state.assume_init())
+        }
+    }
+
+    /// authenticates the concatenation of the given parts.
+    fn authenticate(&self, parts: &[&[u8]]) -> [u8; hmacsha256::TAGBYTES] {
+        let mut state = self.0;
+        let mut tag = [0u8; hmacsha256::TAGBYTES];
+        unsafe {
+            for part in parts {
+                ffi::crypto_auth_hmacsha256_update(&mut state, part.as_ptr(), part.len() as u64);
+            }
+            ffi::crypto_auth_hmacsha256_final(&mut state, tag.as_mut_ptr());
+            ffi::sodium_memzero(&mut state as *mut _ as *mut _, mem::size_of_val(&state));
+        }
+        tag
+    }
+}
+
+impl Drop for KeyedHmacSha256 {
+    fn drop(&mut self) {
+        unsafe { ffi::sodium_memzero(&mut self.0 as *mut _ as *mut _, mem::size_of_val(&self.0)) };
+    }
+}
+
+impl PasswordHasher for Pbkdf2Hasher {
+    fn params(&self) -> KdfParams {
+        KdfParams::Pbkdf2 {
+            iterations: self.iterations,
+        }
+    }
+
+    fn derive(&self, salt: &[u8], password: &str, length: usize) -> PPHResult<Vec<u8>> {
+        check_length(length)?;
+        if self.iterations < 1 {
+            return Err(PPHError {
+                kind: PPHErrorKind::HashError,
+                msg: "pbkdf2 requires at least one iteration".to_string(),
+            });
+        }
+
+        // the output is made of as many blocks of HMAC-SHA256 as needed, truncated to the length,
+        // all keyed with the password once
+        let prf = KeyedHmacSha256::new(password.as_bytes());
+        let mut saltedpasswordhash: Vec<u8> = vec![];
+        let blocks = length.div_ceil(hmacsha256::TAGBYTES);
+        for index in 1..=(blocks as u32) {
+            let mut block = prf.authenticate(&[salt, &index.to_be_bytes()]);
+
+            let mut outputblock = block;
+            for _ in 1..self.iterations {
+                block = prf.authenticate(&[&block]);
+                for (byte, nextbyte) in outputblock.iter_mut().zip(block.iter()) {
+                    *byte ^= nextbyte;
+                }
+            }
+            saltedpasswordhash.extend_from_slice(&outputblock);
+        }
+        saltedpasswordhash.truncate(length);
+        Ok(saltedpasswordhash)
+    }
+}
+
+/// `BcryptHasher` hashes passwords with bcrypt, for populations already using it. As with other
+/// bcrypt implementations, only the first 72 bytes of a password are used. The 24-byte bcrypt
+/// output is expanded to the hash length with BLAKE2b.
+#[derive(Debug, Clone, Copy)]
+pub struct BcryptHasher {
+    pub cost: u32,
+}
+
+impl Default for BcryptHasher {
+    fn default() -> Self {
+        BcryptHasher { cost: 12 }
+    }
+}
+
+/// prepares a password as the key taken by bcrypt, a NUL-terminated string truncated to 72 bytes.
+pub(crate) fn bcrypt_key(password: &str) -> Vec<u8> {
+    let mut key: Vec<u8> = password.as_bytes().to_vec();
+    key.push(0);
+    key.truncate(72);
+    key
+}
+
+impl PasswordHasher for BcryptHasher {
+    fn params(&self) -> KdfParams {
+        KdfParams::Bcrypt { cost: self.cost }
+    }
+
+    fn derive(&self, salt: &[u8], password: &str, length: usize) -> PPHResult<Vec<u8>> {
+        check_length(length)?;
+        if !(4..=31).contains(&self.cost) {
+            return Err(PPHError {
+                kind: PPHErrorKind::HashError,
+                msg: format!("bcrypt cost {} is outside of 4 to 31", self.cost),
+            });
+        }
+
+        let mut bcryptsalt = [0u8; 16];
+        bcryptsalt.copy_from_slice(&fit_salt(salt, 16));
+
+        let output = bcrypt::bcrypt(self.cost, bcryptsalt, &bcrypt_key(password));
+        Ok(expand(&output, length))
+    }
+}
@@ -0,0 +1,308 @@
+//! Defines the legacy password hash formats that accounts can be migrated from without knowing
+//! their passwords: SHA-crypt (`$6$`), bcrypt (`$2a$`, `$2b$`, `$2y$`) and Apache's MD5-crypt
+//! (`$apr1$`).
+//!
+//! A legacy hash is split into its setting, made up of the scheme, cost and salt, and its hash.
+//! The setting is stored with the migrated account, and the hash is used in place of the password,
+//! so that it can be recomputed from the password on login and then verified like one.
+
+use md5::{Digest, Md5};
+use sodiumoxide::crypto::hash::sha512;
+
+use crate::error::{format_error, PPHResult};
+use crate::hasher::bcrypt_key;
+
+/// alphabet used by SHA-crypt and MD5-crypt to encode hashes.
+const CRYPT_ALPHABET: &[u8] = b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// alphabet used by bcrypt to encode salts and hashes.
+const BCRYPT_ALPHABET: &[u8] = b"./ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+/// default and bounds of the number of SHA-crypt rounds.
+const SHA_CRYPT_ROUNDS: u32 = 5000;
+const SHA_CRYPT_MIN_ROUNDS: u32 = 1000;
+const SHA_CRYPT_MAX_ROUNDS: u32 = 999_999_999;
+
+/// encodes a hash as done by SHA-crypt and MD5-crypt, in groups of three bytes given by their
+/// indices in the hash, followed by a single last byte. Each group is written as 24 bits with the
+/// least significant character first.
+fn crypt_encode(hash: &[u8], groups: &[(usize, usize, usize)], last: usize) -> String {
+    let mut encoded = String::new();
+    let mut push = |mut w: u32, chars: usize| {
+        for _ in 0..chars {
+            encoded.push(CRYPT_ALPHABET[(w & 0x3f) as usize] as char);
+            w >>= 6;
+        }
+    };
+    for &(b2, b1, b0) in groups {
+        push(((hash[b2] as u32) << 16) | ((hash[b1] as u32) << 8) | hash[b0] as u32, 4);
+    }
+    push(hash[last] as u32, 2);
+    encoded
+}
+
+/// encodes bytes with the bcrypt alphabet, without padding.
+fn bcrypt_encode(data: &[u8]) -> String {
+    let mut encoded = String::new();
+    for chunk in data.chunks(3) {
+        let mut w: u32 = 0;
+        for (i, byte) in chunk.iter().enumerate() {
+            w |= (*byte as u32) << (16 - 8 * i);
+        }
+        for i in 0..=chunk.len() {
+            encoded.push(BCRYPT_ALPHABET[((w >> (18 - 6 * i)) & 0x3f) as usize] as char);
+        }
+    }
+    encoded
+}
+
+/// decodes text in the bcrypt alphabet into the given number of bytes, ignoring leftover bits.
+fn bcrypt_decode(data: &str, length: usize) -> PPHResult<Vec<u8>> {
+    let mut decoded: Vec<u8> = vec![];
+    let mut bits: u32 = 0;
+    let mut nbits = 0;
+    for c in data.bytes() {
+        let value = match BCRYPT_ALPHABET.iter().position(|a| *a == c) {
+            Some(value) => value as u32,
+            None => return Err(format_error("invalid character in bcrypt salt".to_string())),
+        };
+        bits = (bits << 6) | value;
+        nbits += 6;
+        if nbits >= 8 {
+            nbits -= 8;
+            decoded.push((bits >> nbits) as u8);
+        }
+    }
+    decoded.truncate(length);
+    Ok(decoded)
+}
+
+/// repeats a digest to fill the given length.
+fn repeat_to(digest: &[u8], length: usize) -> Vec<u8> {
+    digest.iter().cycle().take(length).cloned().collect()
+}
+
+/// computes a SHA-crypt (`$6$`) hash from its salt and number of rounds.
+fn sha512_crypt(password: &[u8], salt: &[u8], rounds: u32) -> Vec<u8> {
+    let sha512::Digest(b) = {
+        let mut state = sha512::State::new();
+        state.update(password);
+        state.update(salt);
+        state.update(password);
+        state.finalize()
+    };
+
+    let mut state = sha512::State::new();
+    state.update(password);
+    state.update(salt);
+    state.update(&repeat_to(&b, password.len()));
+    let mut length = password.len();
+    while length > 0 {
+        if length & 1 == 1 {
+            state.update(&b);
+        } else {
+            state.update(password);
+        }
+        length >>= 1;
+    }
+    let sha512::Digest(a) = state.finalize();
+
+    let sha512::Digest(dp) = {
+        let mut state = sha512::State::new();
+        for _ in 0..password.len() {
+            state.update(password);
+        }
+        state.finalize()
+    };
+    let p = repeat_to(&dp, password.len());
+
+    let sha512::Digest(ds) = {
+        let mut state = sha512::State::new();
+        for _ in 0..(16 + a[0] as usize) {
+            state.update(salt);
+        }
+        state.finalize()
+    };
+    let s = repeat_to(&ds, salt.len());
+
+    let mut c = a;
+    for round in 0..rounds {
+        let mut state = sha512::State::new();
+        if round & 1 == 1 {
+            state.update(&p);
+        } else {
+            state.update(&c);
+        }
+        if round % 3 != 0 {
+            state.update(&s);
+        }
+        if round % 7 != 0 {
+            state.update(&p);
+        }
+        if round & 1 == 1 {
+            state.update(&c);
+        } else {
+            state.update(&p);
+        }
+        let sha512::Digest(next) = state.finalize();
+        c = next;
+    }
+    c.to_vec()
+}
+
+/// computes an Apache MD5-crypt (`$apr1$`) hash from its salt.
+fn apr1_crypt(password: &[u8], salt: &[u8]) -> Vec<u8> {
+    let alternate = Md5::new()
+        .chain_update(password)
+        .chain_update(salt)
+        .chain_update(password)
+        .finalize();
+
+    let mut state = Md5::new();
+    state.update(password);
+    state.update(b"$apr1$");
+    state.update(salt);
+    state.update(repeat_to(&alternate, password.len()));
+    let mut length = password.len();
+    while length > 0 {
+        if length & 1 == 1 {
+            state.update([0u8]);
+        } else {
+            state.update(&password[..1]);
+        }
+        length >>= 1;
+    }
+    let mut hash = state.finalize();
+
+    for round in 0..1000 {
+        let mut state = Md5::new();
+        if round & 1 == 1 {
+            state.update(password);
+        } else {
+            state.update(hash);
+        }
+        if round % 3 != 0 {
+            state.update(salt);
+        }
+        if round % 7 != 0 {
+            state.update(password);
+        }
+        if round & 1 == 1 {
+            state.update(hash);
+        } else {
+            state.update(password);
+        }
+        hash = state.finalize();
+    }
+    hash.to_vec()
+}
+
+/// `Setting` is the parsed setting of a legacy hash, holding everything but the hash itself.
+enum Setting {
+    Sha512Crypt { rounds: u32, salt: String },
+    Bcrypt { cost: u32, salt: String },
+    Apr1 { salt: String },
+}
+
+impl Setting {
+    /// parses the setting of a legacy hash, made up of the fields preceding the hash.
+    fn parse(setting: &str) -> PPHResult<Setting> {
+        let fields: Vec<&str> = setting.split('$').collect();
+        match fields.as_slice() {
+            ["", "6", rounds, salt] => {
+                let rounds = rounds
+                    .strip_prefix("rounds=")
+                    .and_then(|rounds| rounds.parse::<u32>().ok())
+                    .ok_or_else(|| format_error("invalid SHA-crypt rounds".to_string()))?;
+                Ok(Setting::Sha512Crypt {
+                    rounds: rounds.clamp(SHA_CRYPT_MIN_ROUNDS, SHA_CRYPT_MAX_ROUNDS),
+                    salt: salt.chars().take(16).collect(),
+                })
+            }
+            ["", "6", salt] => Ok(Setting::Sha512Crypt {
+                rounds: SHA_CRYPT_ROUNDS,
+                salt: salt.chars().take(16).collect(),
+            }),
+            ["", "2a" | "2b" | "2y", cost, salt] => {
+                let cost = match cost.parse::<u32>() {
+                    Ok(value) if cost.len() == 2 && (4..=31).contains(&value) => value,
+                    _ => return Err(format_error("invalid bcrypt cost".to_string())),
+                };
+                if salt.len() != 22 {
+                    return Err(format_error("invalid bcrypt salt".to_string()));
+                }
+                Ok(Setting::Bcrypt {
+                    cost,
+                    salt: salt.to_string(),
+                })
+            }
+            ["", "apr1", salt] => Ok(Setting::Apr1 {
+                salt: salt.chars().take(8).collect(),
+            }),
+            _ => Err(format_error("unsupported legacy password hash".to_string())),
+        }
+    }
+
+    /// returns the length of the encoded hash following the setting.
+    fn hash_length(&self) -> usize {
+        match self {
+            Setting::Sha512Crypt { .. } => 86,
+            Setting::Bcrypt { .. } => 31,
+            Setting::Apr1 { .. } => 22,
+        }
+    }
+}
+
+/// splits a legacy hash into its setting and its hash, checking that it is in a supported format.
+pub fn split(legacyhash: &str) -> PPHResult<(String, String)> {
+    let position = match legacyhash.rfind('$') {
+        Some(position) => position,
+        None => return Err(format_error("not a legacy password hash".to_string())),
+    };
+
+    // bcrypt has no separator between the salt and the hash
+    let (setting, hash) = if legacyhash.starts_with("$2") {
+        if !legacyhash.is_char_boundary(position + 23) {
+            return Err(format_error("invalid bcrypt salt".to_string()));
+        }
+        legacyhash.split_at(position + 23)
+    } else {
+        (&legacyhash[..position], &legacyhash[(position + 1)..])
+    };
+
+    if Setting::parse(setting)?.hash_length() != hash.len() {
+        return Err(format_error("legacy hash has an invalid length".to_string()));
+    }
+    Ok((setting.to_string(), hash.to_string()))
+}
+
+/// recomputes the hash part of a legacy hash from a password and the setting of the hash.
+pub fn compute(setting: &str, password: &str) -> PPHResult<String> {
+    match Setting::parse(setting)? {
+        Setting::Sha512Crypt { rounds, salt } => {
+            let hash = sha512_crypt(password.as_bytes(), salt.as_bytes(), rounds);
+
+            // every group holds bytes i, i + 21 and i + 42, rotated by the group index
+            let groups: Vec<(usize, usize, usize)> = (0..21)
+                .map(|i| match i % 3 {
+                    0 => (i, i + 21, i + 42),
+                    1 => (i + 21, i + 42, i),
+                    _ => (i + 42, i, i + 21),
+                })
+                .collect();
+            Ok(crypt_encode(&hash, &groups, 63))
+        }
+        Setting::Bcrypt { cost, salt } => {
+            let mut bcryptsalt = [0u8; 16];
+            bcryptsalt.copy_from_slice(&bcrypt_decode(&salt, 16)?);
+
+            let hash = bcrypt::bcrypt(cost, bcryptsalt, &bcrypt_key(password));
+            Ok(bcrypt_encode(&hash[..23]))
+        }
+        Setting::Apr1 { salt } => {
+            let hash = apr1_crypt(password.as_bytes(), salt.as_bytes());
+            let groups = [(0, 6, 12), (1, 7, 13), (2, 8, 14), (3, 9, 15), (4, 10, 5)];
+            Ok(crypt_encode(&hash, &groups, 11))
+        }
+    }
+}
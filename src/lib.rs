@@ -1,115 +1,406 @@
 //! Defines main object for secret sharing and authentication with PolyPasswordHasher.
 
 pub mod account;
+pub mod builder;
+pub mod database;
+pub mod encoding;
 pub mod error;
+pub mod hasher;
+pub mod legacy;
 pub mod math;
+pub mod pepper;
+pub mod phc;
+pub mod ristretto;
 pub mod secretshare;
+pub mod vss;
 
+use sodiumoxide::crypto::generichash;
 use sodiumoxide::crypto::hash::sha256;
 use sodiumoxide::randombytes;
+use sodiumoxide::utils;
 
-use crate::account::Account;
+use crate::account::{Account, ShareEntry};
+use crate::builder::PolyPasswordHasherBuilder;
+use crate::database::{Header, PasswordFile, FORMAT_VERSION};
+use crate::encoding::Encoding;
 use crate::error::{PPHError, PPHErrorKind, PPHResult};
+use crate::hasher::{hmac, KdfParams, PasswordHasher};
+use crate::math::Field;
+use crate::pepper::Pepper;
 use crate::secretshare::ShamirSecret;
+use crate::vss::{Commitments, VssMode};
 
 use std::collections::HashMap;
-use std::fs::{self, File};
+use std::convert::TryFrom;
+use std::fs::File;
 use std::io::Write;
 
-// type alias to `Account`s mapping with an ID value
-type Accounts = HashMap<i64, Account>;
+// type alias to `Account`s mapping indexed by username
+type Accounts = HashMap<String, Account>;
+
+// logins accepted through partial verification while locked, indexed by username with the salted
+// password hash to re-verify after unlocking. Each user is remembered once however often they log
+// in, and a user whose logins did not all match is remembered without a hash, as one of them
+// cannot be genuine.
+type TentativeLogins = HashMap<String, Option<Vec<u8>>>;
+
+/// `UnlockReport` describes the outcome of unlocking a database, listing the usernames whose
+/// passwords produced invalid shares that had to be corrected for, and those whose tentative
+/// logins while locked failed full verification and should have their sessions revoked.
+#[derive(Debug, Clone, Default)]
+pub struct UnlockReport {
+    pub invalidshares: Vec<String>,
+    pub rejectedlogins: Vec<String>,
+}
 
 /// main struct interface that provides the high-level abstractions for interacting with the
-/// implementation to create password databases with secret sharing.
-pub struct PolyPasswordHasher {
+/// implementation to create password databases with secret sharing, over the field `F` which
+/// defaults to GF(2^8).
+pub struct PolyPasswordHasher<F: Field = u8> {
     threshold: u8,
     accounts: Accounts,
-    shamirsecretobj: Option<ShamirSecret>,
+    shamirsecretobj: Option<ShamirSecret<F>>,
     knownsecret: bool,
+    secretcheck: Vec<u8>,
+    thresholdlesskey: Option<Vec<u8>>,
     saltsize: u8,
-    nextavailableshare: u8,
+    partialbytes: u8,
+    digestsize: u8,
+    hasher: Box<dyn PasswordHasher>,
+    peppers: HashMap<String, Pepper>,
+    currentpepper: Option<String>,
+    encoding: Encoding,
+    nextavailableshare: u32,
+    nextaccountid: u64,
+    retiredshares: Vec<u16>,
+    tentativelogins: TentativeLogins,
+    vss: Option<VssMode>,
+    commitments: Option<Commitments>,
+    blindingsecretobj: Option<ShamirSecret<F>>,
 }
 
 impl PolyPasswordHasher {
     /// instantiates a new PolyPasswordHasher struct for interaction. It consumes a threshold number of
-    /// keys, and an optional pre-existing password file. If no file is specified, a new instance
-    /// will be created for use.
-    pub fn new(threshold: u8, passwordfile: Option<String>) -> PPHResult<Self> {
-        let mut nextavailableshare: u8 = 1;
-
-        // if no password file is defined, initialize empty object with a randomized password key,
-        // indicating a first-time setup.
-        if passwordfile.is_none() {
-            // initialize rand buffer
-            let buffer = randombytes::randombytes(256);
-
-            // creates a new shamir secret given a threshold and random buffer
-            let shamirsecretobj = ShamirSecret::new(threshold, Some(buffer));
-
-            return Ok(PolyPasswordHasher {
-                threshold,
-                accounts: Accounts::new(),
-                shamirsecretobj: Some(shamirsecretobj),
-                knownsecret: true,
-                saltsize: 16u8,
-                nextavailableshare: 1,
-            });
+    /// keys, an optional pre-existing password file, and an optional number of bytes of each salted
+    /// hash to leak for partial verification (defaulting to none). If no file is specified, a new
+    /// instance will be created for use. Otherwise the settings are read from the header of the
+    /// file, and it is rejected if they do not match the requested ones, except for the threshold,
+    /// which is always read from the header. Other settings are available through
+    /// `PolyPasswordHasherBuilder`.
+    pub fn new(
+        threshold: u8,
+        passwordfile: Option<String>,
+        partialbytes: Option<u8>,
+    ) -> PPHResult<Self> {
+        let mut builder = PolyPasswordHasherBuilder::new(threshold);
+        if let Some(passwordfile) = passwordfile {
+            builder = builder.passwordfile(passwordfile);
         }
+        if let Some(partialbytes) = partialbytes {
+            builder = builder.partialbytes(partialbytes);
+        }
+        builder.build()
+    }
+}
+
+impl<F: Field> PolyPasswordHasher<F> {
+
+    /// generates a new master secret as long as the salted password hashes it protects, along with
+    /// the key used to protect thresholdless accounts and the value used to check the secret when
+    /// it is recovered.
+    fn generate_secret(&mut self) -> PPHResult<()> {
+        // the secret is made of random field elements, so that it reads back unchanged from shares
+        let buffer: Vec<u8> = (0..(self.digestsize as usize / F::BYTES))
+            .flat_map(|_| F::random().to_bytes())
+            .collect();
+        self.thresholdlesskey = Some(Self::derive_thresholdless_key(&buffer));
+        self.secretcheck = Self::secret_check(&buffer);
+        self.shamirsecretobj = Some(ShamirSecret::with_field(self.threshold, Some(buffer))?);
+        self.publish_commitments()
+    }
 
-        let shamirsecretobj = ShamirSecret::new(threshold, None);
+    /// publishes commitments to the polynomials of the master secret, if verifiable secret
+    /// sharing is enabled, generating the blinding polynomials of Pedersen commitments.
+    fn publish_commitments(&mut self) -> PPHResult<()> {
+        let (commitments, blindingsecretobj) =
+            Self::commit_to(self.vss, self.shamirsecretobj.as_ref().unwrap())?;
+        self.commitments = commitments;
+        self.blindingsecretobj = blindingsecretobj;
+        Ok(())
+    }
 
-        // Open file and store content from passwordfile
-        let raw_content = fs::read_to_string(passwordfile.unwrap())?;
+    /// computes the commitments to the polynomials of a secret for the given verifiable secret
+    /// sharing mode, along with the blinding polynomials of Pedersen commitments.
+    fn commit_to(
+        vss: Option<VssMode>,
+        secretobj: &ShamirSecret<F>,
+    ) -> PPHResult<(Option<Commitments>, Option<ShamirSecret<F>>)> {
+        match vss {
+            Some(VssMode::Feldman) => Ok((Some(Commitments::feldman(secretobj)?), None)),
+            Some(VssMode::Pedersen) => {
+                let blindingsecretobj = secretobj.blinding()?;
+                let commitments = Commitments::pedersen(secretobj, &blindingsecretobj)?;
+                Ok((Some(commitments), Some(blindingsecretobj)))
+            }
+            None => Ok((None, None)),
+        }
+    }
 
-        // Use serde to deserialize data from file
-        let accounts: Accounts = serde_json::from_str::<Accounts>(&raw_content)?;
+    #[inline]
+    fn do_bytearray_xor(a: Vec<u8>, b: Vec<u8>) -> PPHResult<Vec<u8>> {
+        if a.len() != b.len() {
+            return Err(PPHError {
+                kind: PPHErrorKind::ShareError,
+                msg: format!("cannot XOR {} bytes with {} bytes", a.len(), b.len()),
+            });
+        }
+        Ok(a.iter().zip(b.iter()).map(|(x, y)| x ^ y).collect())
+    }
 
-        // Grab the id, and the Account struct for each account within the HashMap
-        for (_id, account) in accounts.iter() {
-            nextavailableshare = std::cmp::max(nextavailableshare, account.sharenumber);
+    /// computes the salted password hash of an account with the given hasher parameters, mixing in
+    /// the pepper with the given ID if any.
+    fn salted_hash(
+        &self,
+        kdf: &KdfParams,
+        pepper: Option<&String>,
+        salt: &[u8],
+        password: &str,
+    ) -> PPHResult<Vec<u8>> {
+        let saltedpasswordhash = kdf.hasher().derive(salt, password, self.digestsize as usize)?;
+        let pepper = match pepper {
+            Some(pepper) => pepper,
+            None => return Ok(saltedpasswordhash),
+        };
+        match self.peppers.get(pepper) {
+            Some(pepper) => Ok(pepper.mix(&saltedpasswordhash)),
+            None => Err(PPHError {
+                kind: PPHErrorKind::PepperError,
+                msg: format!("pepper {} is not available", pepper),
+            }),
         }
+    }
 
-        nextavailableshare += 1;
+    /// computes the salted password hash of an existing account from a password. For accounts
+    /// migrated from a legacy hash, the legacy hash is recomputed from the password and hashed in
+    /// its place.
+    fn account_hash(&self, account: &Account, password: &str) -> PPHResult<Vec<u8>> {
+        let password = match &account.legacy {
+            Some(setting) => legacy::compute(setting, password)?,
+            None => password.to_string(),
+        };
+        self.salted_hash(&account.kdf, account.pepper.as_ref(), &account.salt, &password)
+    }
 
-        Ok(Self {
-            threshold,
-            accounts,
-            shamirsecretobj: Some(shamirsecretobj),
-            knownsecret: false,
-            saltsize: 16u8,
-            nextavailableshare
-        })
+    /// derives the hash protected by a single share entry from the salted password hash of its
+    /// account, so that the entries of a user holding several shares cannot be XORed together to
+    /// learn how their shares relate.
+    fn entry_hash(saltedpasswordhash: &[u8], x: F) -> Vec<u8> {
+        let mut state =
+            generichash::State::new(Some(saltedpasswordhash.len()), Some(saltedpasswordhash))
+                .unwrap();
+        state.update(&x.to_bytes()).unwrap();
+        state.finalize().unwrap().as_ref().to_vec()
     }
 
+    /// derives the key protecting the blinding share of a single share entry from the salted
+    /// password hash of its account, distinct from the hash protected by the entry.
+    fn blinding_hash(saltedpasswordhash: &[u8], x: F) -> Vec<u8> {
+        let mut state =
+            generichash::State::new(Some(saltedpasswordhash.len()), Some(saltedpasswordhash))
+                .unwrap();
+        state.update(&x.to_bytes()).unwrap();
+        state.update(b"polypasswordhasher blinding").unwrap();
+        state.finalize().unwrap().as_ref().to_vec()
+    }
+
+    /// splits a stored hash into the protected salted hash and the leaked partial bytes.
     #[inline]
-    fn do_bytearray_xor(a: Vec<u8>, b: Vec<u8>) -> Vec<u8> {
-        if a.len() != b.len() {
-            panic!("{:?} {:?}, {:?} {:?}", a.len(), b.len(), a, b);
+    fn split_passhash<'a>(&self, passhash: &'a [u8]) -> (&'a [u8], &'a [u8]) {
+        passhash.split_at(passhash.len() - self.partialbytes as usize)
+    }
+
+    /// returns the length of the protected hashes stored in share entries.
+    #[inline]
+    fn passhash_length(&self) -> usize {
+        self.digestsize as usize + self.partialbytes as usize
+    }
+
+    /// returns the trailing bytes of a salted hash that are leaked for partial verification.
+    #[inline]
+    fn partial_bytes<'a>(&self, saltedpasswordhash: &'a [u8]) -> &'a [u8] {
+        &saltedpasswordhash[(saltedpasswordhash.len() - self.partialbytes as usize)..]
+    }
+
+    /// fully verifies a salted password hash against an account by re-protecting it for the
+    /// account's first entry. Every entry is protected from the same salted hash, so checking one
+    /// is enough.
+    fn verify_account(&self, account: &Account, saltedpasswordhash: &[u8]) -> PPHResult<bool> {
+        let entry = &account.entries[0];
+        let passhash = self.protect_entry(entry.sharenumber, &account.salt, saltedpasswordhash)?;
+        Ok(utils::memcmp(&passhash, &entry.passhash))
+    }
+
+    /// computes the value recorded in the password file to check that a secret recovered from
+    /// shares is the master secret, as a keyed hash that does not reveal the secret itself.
+    fn secret_check(secretdata: &[u8]) -> Vec<u8> {
+        let mut state =
+            generichash::State::new(Some(generichash::DIGEST_MAX), Some(secretdata)).unwrap();
+        state.update(b"polypasswordhasher secret check").unwrap();
+        state.finalize().unwrap().as_ref().to_vec()
+    }
+
+    /// derives the key used to protect thresholdless accounts from the master secret.
+    fn derive_thresholdless_key(secretdata: &[u8]) -> Vec<u8> {
+        let sha256::Digest(key) = sha256::hash(secretdata);
+        key.to_vec()
+    }
+
+    /// protects (or unprotects) the salted password hash of a thresholdless account by XORing it
+    /// with a keystream derived from the thresholdless key and the account's salt.
+    fn thresholdless_protect(
+        &self,
+        salt: &[u8],
+        saltedpasswordhash: Vec<u8>,
+    ) -> PPHResult<Vec<u8>> {
+        let key = match &self.thresholdlesskey {
+            Some(key) => key,
+            None => {
+                return Err(PPHError {
+                    kind: PPHErrorKind::AuthError,
+                    msg: "password file is locked".to_string(),
+                })
+            }
+        };
+        let mask = hmac(key, salt, saltedpasswordhash.len());
+        Self::do_bytearray_xor(saltedpasswordhash, mask)
+    }
+
+    /// returns the element of the field used as x for the given share number.
+    fn share_x(sharenumber: u16) -> PPHResult<F> {
+        F::try_from(sharenumber).map_err(|_| PPHError {
+            kind: PPHErrorKind::ShardError,
+            msg: format!("share number {} is outside of the field", sharenumber),
+        })
+    }
+
+    /// computes the share of a secret with the given share number, and strips the leading share
+    /// number from it to leave the share data, as long as the secret.
+    fn share_data(secretobj: &ShamirSecret<F>, sharenumber: u16) -> PPHResult<Vec<u8>> {
+        let x = Self::share_x(sharenumber)?;
+        let share = secretobj.compute_share(x)?;
+        if share.len() < F::BYTES || F::from_bytes(&share) != x {
+            return Err(PPHError {
+                kind: PPHErrorKind::ShareError,
+                msg: format!("share {} is malformed", sharenumber),
+            });
         }
+        Ok(share[F::BYTES..].to_vec())
+    }
 
-        let mut result = vec![];
-        for position in 0..a.len() {
-            result.push(a[position] ^ b[position]);
+    /// protects the salted password hash of an account for a single entry, either with the share
+    /// data of the given share number (without the leading share number) or with the thresholdless
+    /// key for share number 0. The bytes leaked for partial verification are appended.
+    fn protect_entry(
+        &self,
+        sharenumber: u16,
+        salt: &[u8],
+        saltedpasswordhash: &[u8],
+    ) -> PPHResult<Vec<u8>> {
+        let mut passhash: Vec<u8> = if sharenumber == 0 {
+            self.thresholdless_protect(salt, saltedpasswordhash.to_vec())?
+        } else {
+            Self::do_bytearray_xor(
+                Self::entry_hash(
+                    saltedpasswordhash,
+                    Self::share_x(sharenumber)?,
+                ),
+                Self::share_data(self.shamirsecretobj.as_ref().unwrap(), sharenumber)?,
+            )?
+        };
+        passhash.extend_from_slice(self.partial_bytes(saltedpasswordhash));
+        Ok(passhash)
+    }
+
+    /// protects the blinding share with the given share number (without the leading share number)
+    /// for a single entry, if the database has Pedersen commitments and the entry is not
+    /// thresholdless.
+    fn protect_blinding(
+        &self,
+        sharenumber: u16,
+        saltedpasswordhash: &[u8],
+    ) -> PPHResult<Option<Vec<u8>>> {
+        let blindingsecretobj = match &self.blindingsecretobj {
+            Some(blindingsecretobj) if sharenumber != 0 => blindingsecretobj,
+            _ => return Ok(None),
+        };
+        let blinding = Self::do_bytearray_xor(
+            Self::blinding_hash(saltedpasswordhash, Self::share_x(sharenumber)?),
+            Self::share_data(blindingsecretobj, sharenumber)?,
+        )?;
+        Ok(Some(blinding))
+    }
+
+    /// protects a salted password hash for each of the given share numbers, or for a single
+    /// thresholdless entry if no share numbers are given.
+    fn protect_entries(
+        &self,
+        sharenumbers: Vec<u16>,
+        salt: &[u8],
+        saltedpasswordhash: &[u8],
+    ) -> PPHResult<Vec<ShareEntry>> {
+        let sharenumbers: Vec<u16> = if sharenumbers.is_empty() { vec![0] } else { sharenumbers };
+
+        let mut entries: Vec<ShareEntry> = vec![];
+        for sharenumber in sharenumbers {
+            let passhash = self.protect_entry(sharenumber, salt, saltedpasswordhash)?;
+            let blinding = self.protect_blinding(sharenumber, saltedpasswordhash)?;
+            entries.push(ShareEntry { sharenumber, passhash, blinding });
         }
-        result
+        Ok(entries)
     }
 
-    /// create a new user given a set of credentials and the minimum number of shares necessary to
-    /// reconstruct the original master password.
+    /// create a new user given a set of credentials and the number of shares of the master secret
+    /// that the user should hold. Users created with 0 shares are thresholdless accounts, which
+    /// cannot be used to unlock the database but do not consume any share numbers.
     pub fn create_account(
         &mut self,
         username: String,
         password: String,
         shares: u8,
+    ) -> PPHResult<()> {
+        self.add_account(username, password, shares, None)
+    }
+
+    /// migrates a user whose plaintext password is unknown from a legacy password hash, either
+    /// SHA-crypt (`$6$`), bcrypt (`$2b$`) or Apache MD5-crypt (`$apr1$`). The legacy hash is
+    /// hashed and protected in place of the password, and recomputed from the password on login.
+    /// The account is upgraded to a native one on its next successful login to an unlocked
+    /// database.
+    pub fn migrate_account(
+        &mut self,
+        username: String,
+        legacyhash: String,
+        shares: u8,
+    ) -> PPHResult<()> {
+        let (setting, hash) = legacy::split(&legacyhash)?;
+        self.add_account(username, hash, shares, Some(setting))
+    }
+
+    /// adds a new user, protecting the salted hash of the given password with the given number of
+    /// shares, and recording the setting of the legacy hash it stands for, if any.
+    fn add_account(
+        &mut self,
+        username: String,
+        password: String,
+        shares: u8,
+        legacy: Option<String>,
     ) -> PPHResult<()> {
         // check if username already exists
-        for (_, account) in self.accounts.iter() {
-            if account.username == username {
-                return Err(PPHError {
-                    kind: PPHErrorKind::AuthError,
-                    msg: "username already exists in database".to_string(),
-                });
-            }
+        if self.accounts.contains_key(&username) {
+            return Err(PPHError {
+                kind: PPHErrorKind::AuthError,
+                msg: "username already exists in database".to_string(),
+            });
         }
 
         if !self.knownsecret {
@@ -119,44 +410,57 @@ impl PolyPasswordHasher {
             });
         }
 
-        for sharenumber in self.nextavailableshare..(self.nextavailableshare + shares) {
-            let shamirsecretdata = self
-                .shamirsecretobj
-                .clone()
-                .unwrap()
-                .compute_share(sharenumber);
-
-            // initialize rand buffer
-            let salt_buffer: Vec<u8> = randombytes::randombytes(self.saltsize as usize);
-
-            // initialize salted password hash
-            let salt: String = String::from_utf8(salt_buffer).unwrap();
-            let saltpass: String = format!("{}{}", salt, password);
-            let sha256::Digest(saltedpasswordhash) = sha256::hash(&saltpass.as_bytes());
-
-            let mut passhash: Vec<u8> =
-                PolyPasswordHasher::do_bytearray_xor(saltedpasswordhash.to_vec(), shamirsecretdata);
-            passhash.push(saltedpasswordhash[saltedpasswordhash.len()]);
-
-            // initialize new account entry and add to dict
-            let new_account = Account {
-                id: 0, // TODO: change!
-                username: username.clone(),
-                salt,
-                sharenumber,
-                passhash: String::from_utf8(passhash).unwrap(),
-            };
-            self.accounts.insert(new_account.id, new_account);
+        // the share numbers of the account must all be elements of the field
+        let lastshare = self.nextavailableshare + shares as u32;
+        let available = u16::try_from(lastshare - 1)
+            .ok()
+            .and_then(|sharenumber| F::try_from(sharenumber).ok())
+            .is_some();
+        if shares > 0 && !available {
+            return Err(PPHError {
+                kind: PPHErrorKind::ShardError,
+                msg: "not enough share numbers are left in the field".to_string(),
+            });
         }
 
-        // Iterate nextavailableshare
-        self.nextavailableshare += shares;
+        // initialize rand buffer and salted password hash, and protect it with each share, or
+        // with a key derived from the secret for thresholdless accounts
+        let salt: Vec<u8> = randombytes::randombytes(self.saltsize as usize);
+        let kdf = self.hasher.params();
+        let pepper = self.currentpepper.clone();
+        let saltedpasswordhash = self.salted_hash(&kdf, pepper.as_ref(), &salt, &password)?;
+        let sharenumbers: Vec<u16> =
+            (self.nextavailableshare..lastshare).map(|sharenumber| sharenumber as u16).collect();
+        let entries = self.protect_entries(sharenumbers, &salt, &saltedpasswordhash)?;
+
+        // initialize new account and add to dict
+        let new_account = Account {
+            id: self.nextaccountid,
+            username: username.clone(),
+            salt,
+            kdf,
+            pepper,
+            legacy,
+            refreshed: false,
+            entries,
+        };
+        self.accounts.insert(username, new_account);
+
+        // Iterate nextavailableshare and nextaccountid
+        self.nextavailableshare = lastshare;
+        self.nextaccountid += 1;
         Ok(())
     }
 
-    /// helper used to determine if a username/password can authenticate correctly
-    pub fn is_valid_login(&self, username: String, password: String) -> PPHResult<bool> {
-        // initial error-checking
+    /// changes the password of a user after verifying the old one. Every entry held by the user is
+    /// re-salted and re-protected under its existing share number, leaving the threshold structure
+    /// of the database unchanged.
+    pub fn change_password(
+        &mut self,
+        username: String,
+        oldpassword: String,
+        newpassword: String,
+    ) -> PPHResult<()> {
         if !self.knownsecret {
             return Err(PPHError {
                 kind: PPHErrorKind::AuthError,
@@ -164,64 +468,426 @@ impl PolyPasswordHasher {
             });
         }
 
-        // collect usernames from accounts into a vector
-        let username_vec: Vec<String> = self.accounts.iter()
-            .map(|(_id, acc)| acc.clone().username)
-            .collect::<Vec<String>>();
+        if !self.is_valid_login(username.clone(), oldpassword)? {
+            return Err(PPHError {
+                kind: PPHErrorKind::AuthError,
+                msg: "old password is incorrect".to_string(),
+            });
+        }
 
-        // check if username exists within the database
-        if !username_vec.contains(&username) {
+        self.rehash_account(&username, &newpassword)
+    }
+
+    /// re-salts and re-hashes the password of a user with the current hasher, and re-protects
+    /// every entry held by the user under its existing share number.
+    fn rehash_account(&mut self, username: &str, password: &str) -> PPHResult<()> {
+        let salt: Vec<u8> = randombytes::randombytes(self.saltsize as usize);
+        let kdf = self.hasher.params();
+        let pepper = self.currentpepper.clone();
+        let saltedpasswordhash = self.salted_hash(&kdf, pepper.as_ref(), &salt, password)?;
+        let sharenumbers = self.accounts[username].sharenumbers();
+        let entries = self.protect_entries(sharenumbers, &salt, &saltedpasswordhash)?;
+
+        let account = self.accounts.get_mut(username).unwrap();
+        account.salt = salt;
+        account.kdf = kdf;
+        account.pepper = pepper;
+        account.legacy = None;
+        account.refreshed = false;
+        account.entries = entries;
+        Ok(())
+    }
+
+    /// removes a user from the database, dropping every entry it holds. The share numbers held by
+    /// the user are retired so that they are never reissued, and the removal is refused if the
+    /// remaining shares would no longer be enough to reach the threshold.
+    pub fn remove_account(&mut self, username: String) -> PPHResult<()> {
+        let mut removedshares: Vec<u16> = match self.accounts.get(&username) {
+            Some(account) => account.sharenumbers(),
+            None => {
+                return Err(PPHError {
+                    kind: PPHErrorKind::AuthError,
+                    msg: "username is not known to database".to_string(),
+                })
+            }
+        };
+
+        // count the shares that would remain held by every other account
+        let remainingshares: usize = self.accounts
+            .values()
+            .filter(|acc| acc.username != username)
+            .map(|acc| acc.sharenumbers().len())
+            .sum();
+
+        if !removedshares.is_empty() && remainingshares < self.threshold as usize {
+            return Err(PPHError {
+                kind: PPHErrorKind::ShardError,
+                msg: "removing account would make the threshold unreachable".to_string(),
+            });
+        }
+
+        self.retiredshares.append(&mut removedshares);
+        self.accounts.remove(&username);
+        self.tentativelogins.remove(&username);
+        Ok(())
+    }
+
+    /// helper used to determine if a username/password can authenticate correctly. While the
+    /// database is locked, logins can only be checked against the leaked partial bytes, and are
+    /// tentatively accepted until the database is unlocked and they can be fully re-verified.
+    /// Successful logins to an unlocked database re-hash the account with the current hasher and
+    /// pepper if it was hashed with different ones, migrated from a legacy hash or not re-salted
+    /// since its shares were refreshed, keeping its share numbers.
+    pub fn is_valid_login(&mut self, username: String, password: String) -> PPHResult<bool> {
+        // initial error-checking
+        if !self.knownsecret && self.partialbytes == 0 {
             return Err(PPHError {
                 kind: PPHErrorKind::AuthError,
-                msg: "username is not known to database".to_string(),
+                msg: "password file is locked".to_string(),
             });
         }
 
-        for (_id, account) in self.accounts.iter() {
-            let saltpass: String = format!("{}{}", account.salt, password);
-            let sha256::Digest(saltedpasswordhash) = sha256::hash(&saltpass.as_bytes());
+        // check if username exists within the database
+        let account = match self.accounts.get(&username) {
+            Some(account) => account,
+            None => {
+                return Err(PPHError {
+                    kind: PPHErrorKind::AuthError,
+                    msg: "username is not known to database".to_string(),
+                })
+            }
+        };
+
+        let saltedpasswordhash = self.account_hash(account, &password)?;
 
-            if !self.knownsecret {
-                let saltedcheck = saltedpasswordhash[saltedpasswordhash.len()];
-                let entrycheck =
-                    account.clone().passhash.into_bytes()[account.clone().passhash.len()];
-                return Ok(saltedcheck == entrycheck);
+        // only the partial bytes can be checked while locked, so remember the login in order to
+        // fully verify it once the secret is recovered
+        if !self.knownsecret {
+            let (_, partialhash) = self.split_passhash(&account.entries[0].passhash);
+            if partialhash != self.partial_bytes(&saltedpasswordhash) {
+                return Ok(false);
             }
+            let tentativehash = match self.tentativelogins.get(&username) {
+                Some(Some(previoushash)) if utils::memcmp(previoushash, &saltedpasswordhash) => {
+                    Some(saltedpasswordhash)
+                }
+                Some(_) => None,
+                None => Some(saltedpasswordhash),
+            };
+            self.tentativelogins.insert(username, tentativehash);
+            return Ok(true);
+        }
+
+        if !self.verify_account(account, &saltedpasswordhash)? {
+            return Ok(false);
+        }
+
+        // re-hash legacy accounts, accounts with outdated parameters or pepper, and accounts
+        // still under the salt they had before their shares were refreshed, now that the
+        // password is known
+        if account.legacy.is_some()
+            || account.refreshed
+            || account.kdf != self.hasher.params()
+            || account.pepper != self.currentpepper
+        {
+            self.rehash_account(&username, &password)?;
+        }
+        Ok(true)
+    }
+
+    /// exports an account as a PHC-style password string, to be stored in a single column of an
+    /// existing user table.
+    pub fn export_account(&self, username: String) -> PPHResult<String> {
+        match self.accounts.get(&username) {
+            Some(account) => phc::encode_account(account),
+            None => Err(PPHError {
+                kind: PPHErrorKind::AuthError,
+                msg: "username is not known to database".to_string(),
+            }),
+        }
+    }
+
+    /// imports an account from a PHC-style password string exported from this database. Its share
+    /// numbers must not be held by another account or retired, and are no longer issued to new
+    /// accounts. PHC strings do not hold blinding shares, so accounts cannot be imported into a
+    /// database with Pedersen commitments.
+    pub fn import_account(&mut self, username: String, passwordstring: &str) -> PPHResult<()> {
+        if self.accounts.contains_key(&username) {
+            return Err(PPHError {
+                kind: PPHErrorKind::AuthError,
+                msg: "username already exists in database".to_string(),
+            });
+        }
+        if self.vss == Some(VssMode::Pedersen) {
+            return Err(PPHError {
+                kind: PPHErrorKind::FormatError,
+                msg: "accounts cannot be imported into a database with Pedersen commitments"
+                    .to_string(),
+            });
+        }
 
-            let sharedata = PolyPasswordHasher::do_bytearray_xor(
-                saltedpasswordhash.to_vec(),
-                account.clone().passhash.into_bytes()[0..(account.clone().passhash.len())].to_vec(),
-            );
+        let account = phc::decode_account(self.nextaccountid, username.clone(), passwordstring)?;
+        if account.entries.iter().any(|entry| entry.passhash.len() != self.passhash_length()) {
+            return Err(PPHError {
+                kind: PPHErrorKind::FormatError,
+                msg: "protected hash length does not match the database".to_string(),
+            });
+        }
+
+        let sharenumbers = account.sharenumbers();
+        if sharenumbers.iter().any(|sharenumber| F::try_from(*sharenumber).is_err()) {
+            return Err(PPHError {
+                kind: PPHErrorKind::ShardError,
+                msg: "share numbers of the account are outside of the field".to_string(),
+            });
+        }
+        let heldshares: Vec<u16> = self.accounts
+            .values()
+            .flat_map(|account| account.sharenumbers())
+            .chain(self.retiredshares.iter().cloned())
+            .collect();
+        if sharenumbers.iter().any(|sharenumber| heldshares.contains(sharenumber)) {
+            return Err(PPHError {
+                kind: PPHErrorKind::ShardError,
+                msg: "share numbers of the account are already in use".to_string(),
+            });
+        }
+
+        if let Some(lastshare) = sharenumbers.iter().max() {
+            self.nextavailableshare = self.nextavailableshare.max(*lastshare as u32 + 1);
+        }
+        self.accounts.insert(username, account);
+        self.nextaccountid += 1;
+        Ok(())
+    }
+
+    /// re-randomizes the shares of the master secret on an unlocked database, without changing
+    /// the secret itself. Every threshold entry is re-protected under its new share by swapping
+    /// the old share out of its protected hash, so no passwords are needed. Commitments are
+    /// published again for the new shares.
+    ///
+    /// The salted hash under a re-protected entry is unchanged until the account is re-salted on
+    /// its next successful login to an unlocked database, so an entry leaked before the refresh
+    /// XORed with its refreshed copy yields the difference of the old and new shares alone.
+    /// Entries of accounts that have not logged in since should be treated as exposed if the
+    /// old password file leaked.
+    pub fn refresh_shares(&mut self) -> PPHResult<()> {
+        self.reshare(self.threshold)
+    }
+
+    /// changes the threshold of an unlocked database, re-sharing the master secret at the new
+    /// degree and re-protecting every threshold entry under its new share as done by
+    /// `refresh_shares()`. The change is refused if the shares held by all accounts together
+    /// would not reach the new threshold. The new threshold is recorded in the password file
+    /// when it is committed.
+    pub fn set_threshold(&mut self, threshold: u8) -> PPHResult<()> {
+        if threshold < 1 {
+            return Err(PPHError {
+                kind: PPHErrorKind::ShardError,
+                msg: "threshold must be at least 1".to_string(),
+            });
+        }
+
+        let heldshares: usize =
+            self.accounts.values().map(|account| account.sharenumbers().len()).sum();
+        if heldshares < threshold as usize {
+            return Err(PPHError {
+                kind: PPHErrorKind::ShardError,
+                msg: format!(
+                    "{} shares are held by accounts, which cannot reach a threshold of {}",
+                    heldshares, threshold
+                ),
+            });
+        }
+        self.reshare(threshold)
+    }
+
+    /// re-shares the master secret of an unlocked database with the given threshold, and swaps
+    /// the new shares into every threshold entry. The database is only changed once every entry
+    /// has been re-protected, so it is left as it was if re-sharing fails.
+    fn reshare(&mut self, threshold: u8) -> PPHResult<()> {
+        if !self.knownsecret {
+            return Err(PPHError {
+                kind: PPHErrorKind::AuthError,
+                msg: "password file is locked".to_string(),
+            });
+        }
 
-            // TODO : implement thresholdless account support
-            let mut share: Vec<u8> = vec![account.sharenumber];
-            for element in sharedata.iter() {
-                share.push(*element);
+        let oldsecretobj = self.shamirsecretobj.as_ref().unwrap();
+        let mut newsecretobj = oldsecretobj.clone();
+        newsecretobj.reshare(threshold)?;
+        let (commitments, blindingsecretobj) = Self::commit_to(self.vss, &newsecretobj)?;
+
+        // entries are re-protected under the salted hash they already have, so mark the accounts
+        // holding shares to be re-salted on their next login
+        let mut refreshed: Vec<(String, Vec<ShareEntry>)> = vec![];
+        for (username, account) in self.accounts.iter() {
+            if account.is_thresholdless() {
+                continue;
             }
-            let shamir = self.shamirsecretobj.clone().unwrap();
-            if shamir.is_valid_share(share) {
-                return Ok(true);
+            let mut entries = account.entries.clone();
+            for entry in entries.iter_mut().filter(|entry| entry.sharenumber != 0) {
+                entry.passhash = self.reprotect(
+                    &entry.passhash,
+                    oldsecretobj,
+                    &newsecretobj,
+                    entry.sharenumber,
+                )?;
+                if let (Some(blinding), Some(oldblinding), Some(newblinding)) = (
+                    &entry.blinding,
+                    &self.blindingsecretobj,
+                    &blindingsecretobj,
+                ) {
+                    let sharenumber = entry.sharenumber;
+                    entry.blinding =
+                        Some(self.reprotect(blinding, oldblinding, newblinding, sharenumber)?);
+                }
             }
+            refreshed.push((username.clone(), entries));
+        }
+        for (username, entries) in refreshed {
+            let account = self.accounts.get_mut(&username).unwrap();
+            account.refreshed = true;
+            account.entries = entries;
+        }
+        self.shamirsecretobj = Some(newsecretobj);
+        self.threshold = threshold;
+        self.commitments = commitments;
+        self.blindingsecretobj = blindingsecretobj;
+        Ok(())
+    }
+
+    /// swaps the share data of one secret for another's in a protected hash or blinding share,
+    /// keeping any partial bytes appended to it.
+    fn reprotect(
+        &self,
+        protected: &[u8],
+        oldsecretobj: &ShamirSecret<F>,
+        newsecretobj: &ShamirSecret<F>,
+        sharenumber: u16,
+    ) -> PPHResult<Vec<u8>> {
+        let (protectedhash, partialhash) = protected.split_at(self.digestsize as usize);
+        let mut reprotected = Self::do_bytearray_xor(
+            Self::do_bytearray_xor(
+                protectedhash.to_vec(),
+                Self::share_data(oldsecretobj, sharenumber)?,
+            )?,
+            Self::share_data(newsecretobj, sharenumber)?,
+        )?;
+        reprotected.extend_from_slice(partialhash);
+        Ok(reprotected)
+    }
+
+    /// sets the encoding used for salts and protected hashes the next time the database is
+    /// committed. Databases loaded from a file keep the encoding they were written with.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    /// sets the length of the salted password hashes, which must be one of `DIGEST_SIZES` and a
+    /// whole number of field elements, as checked by `PolyPasswordHasherBuilder`, and defaults to
+    /// 32 bytes. The master secret is as long as the hashes it protects, so it is generated
+    /// again, and the digest size can only be changed before any account is created.
+    pub fn set_digest_size(&mut self, digestsize: u8) -> PPHResult<()> {
+        PolyPasswordHasherBuilder::<F>::validate(
+            self.threshold,
+            self.saltsize,
+            self.partialbytes,
+            digestsize,
+        )?;
+        if !self.knownsecret || !self.accounts.is_empty() {
+            return Err(PPHError {
+                kind: PPHErrorKind::HashError,
+                msg: "digest size can only be changed on a new database".to_string(),
+            });
+        }
+        self.digestsize = digestsize;
+        self.generate_secret()
+    }
+
+    /// sets the hasher used to compute salted password hashes, which defaults to Argon2id. Each
+    /// account records the hasher it was hashed with, so existing accounts stay valid, and are
+    /// transparently re-hashed with the new hasher on their next successful login. The hasher is
+    /// recorded in the password file and restored when it is loaded.
+    pub fn set_hasher(&mut self, hasher: Box<dyn PasswordHasher>) {
+        self.hasher = hasher;
+    }
+
+    /// adds a pepper, a secret key kept outside of the password file that is mixed into the salted
+    /// hash of every account, and makes it the current pepper for new accounts and re-hashed
+    /// ones. Previously added peppers stay available to verify the accounts still using them,
+    /// which move to the current pepper on their next successful login. Peppers are not written
+    /// to the password file, so they must be added again whenever it is loaded.
+    pub fn add_pepper(&mut self, pepper: Pepper) {
+        self.currentpepper = Some(pepper.id.clone());
+        self.peppers.insert(pepper.id.clone(), pepper);
+    }
+
+    /// removes a pepper once no account uses it anymore.
+    pub fn remove_pepper(&mut self, id: &str) -> PPHResult<()> {
+        if self.accounts.values().any(|account| account.pepper.as_deref() == Some(id)) {
+            return Err(PPHError {
+                kind: PPHErrorKind::PepperError,
+                msg: format!("pepper {} is still used by accounts", id),
+            });
         }
-        Ok(false)
+        self.peppers.remove(id);
+        if self.currentpepper.as_deref() == Some(id) {
+            self.currentpepper = None;
+        }
+        Ok(())
     }
 
     /// given the current state of the accounts stored in-memory, commit it to a persistent file
     /// for storage.
     pub fn commit(&mut self, passwordfile: String) -> PPHResult<()> {
-        if self.threshold >= self.nextavailableshare {
+        if self.threshold as u32 >= self.nextavailableshare {
             return Err(PPHError {
                 kind: PPHErrorKind::ShardError,
                 msg: "must have more shares in order to write".to_string(),
             });
         }
-        let mut file = File::open(passwordfile.as_str()).unwrap();
-        let raw_accounts = serde_json::to_string::<Accounts>(&self.accounts).unwrap();
+        let header = Header {
+            version: FORMAT_VERSION,
+            threshold: self.threshold,
+            saltsize: self.saltsize,
+            partialbytes: self.partialbytes,
+            digestsize: self.digestsize,
+            field: F::KIND,
+            kdf: self.hasher.params(),
+            encoding: self.encoding,
+            nextavailableshare: self.nextavailableshare,
+            nextaccountid: self.nextaccountid,
+            retiredshares: self.retiredshares.clone(),
+            secretcheck: self.encoding.encode(&self.secretcheck),
+            commitments: self.commitments
+                .as_ref()
+                .map(|commitments| commitments.encode(self.encoding)),
+        };
+        let contents = PasswordFile {
+            header,
+            accounts: self.accounts
+                .iter()
+                .map(|(username, account)| (username.clone(), account.encode(self.encoding)))
+                .collect(),
+        };
+
+        let mut file = File::create(passwordfile.as_str())?;
+        let raw_accounts = serde_json::to_string::<PasswordFile>(&contents)?;
         file.write_all(raw_accounts.as_bytes())?;
         Ok(())
     }
 
-    pub fn unlock_database(&mut self, logindata: Vec<(String, String)>) -> PPHResult<()> {
+    /// recovers the master secret from the shares of the given threshold account logins, unlocking
+    /// the database. More logins than the threshold may be given, in which case shares from
+    /// mistyped passwords are corrected for as long as no more than half of the extra shares are
+    /// invalid. With verifiable secret sharing, shares that do not match the commitments in the
+    /// password file are set aside before recovering, however many there are. If the secret
+    /// cannot be recovered or does not match the check value in the password file, a
+    /// `RecoveryError` is returned and the database stays locked. Logins that
+    /// were tentatively accepted while locked are then fully verified, and an `UnlockReport` is
+    /// returned.
+    pub fn unlock_database(&mut self, logindata: Vec<(String, String)>) -> PPHResult<UnlockReport> {
         if self.knownsecret {
             return Err(PPHError {
                 kind: PPHErrorKind::ShardError,
@@ -229,46 +895,128 @@ impl PolyPasswordHasher {
             });
         }
         let mut sharelist = vec![];
+        let mut blindinglist: Vec<Vec<u8>> = vec![];
+        let mut shareowners: HashMap<u16, String> = HashMap::new();
 
         for (username, password) in logindata {
-            let mut username_vec: Vec<String> = vec![];
-            for (_id, account) in self.accounts.iter() {
-                username_vec.push(account.clone().username);
+            // each user only contributes their shares once
+            if shareowners.values().any(|owner| *owner == username) {
+                continue;
             }
 
-            if !username_vec.contains(&username) {
-                return Err(PPHError {
-                    kind: PPHErrorKind::ShardError,
-                    msg: "username is unknown to database".to_string(),
-                });
-            }
+            let account = match self.accounts.get(&username) {
+                Some(account) => account,
+                None => {
+                    return Err(PPHError {
+                        kind: PPHErrorKind::ShardError,
+                        msg: "username is unknown to database".to_string(),
+                    })
+                }
+            };
 
-            for (_id, account) in self.accounts.iter() {
-                if account.username == username {
-                    if account.sharenumber == 0 {
-                        continue;
-                    }
+            // concat the salt and the password, and unprotect the share held by each entry
+            let thissaltedpasswordhash = self.account_hash(account, &password)?;
+            for entry in account.entries.iter() {
+                // thresholdless accounts hold no shares to recover the secret with
+                if entry.sharenumber == 0 {
+                    continue;
+                }
 
-                    // concat the salt and the password
-                    let saltpass: String = format!("{}{}", account.salt, password);
-                    let sha256::Digest(thissaltedpasswordhash) = sha256::hash(&saltpass.as_bytes());
-                    let sharedata = PolyPasswordHasher::do_bytearray_xor(
-                        thissaltedpasswordhash.to_vec(),
-                        account.clone().passhash.into_bytes()[0..(account.clone().passhash.len())]
-                            .to_vec(),
-                    );
-
-                    let mut thisshare = vec![account.sharenumber];
-                    thisshare.extend(sharedata.iter().cloned());
-                    sharelist.push(thisshare);
+                let (protectedhash, _) = self.split_passhash(&entry.passhash);
+                let x = Self::share_x(entry.sharenumber)?;
+                let sharedata = Self::do_bytearray_xor(
+                    Self::entry_hash(&thissaltedpasswordhash, x),
+                    protectedhash.to_vec(),
+                )?;
+
+                // prefix the share data with its share number to form the share
+                let mut thisshare = x.to_bytes();
+                thisshare.extend(sharedata.iter().cloned());
+                sharelist.push(thisshare);
+
+                // unprotect the blinding share as well for Pedersen commitments, leaving it
+                // without data if the entry has none so that it fails verification
+                if self.vss == Some(VssMode::Pedersen) {
+                    let mut blindingshare = x.to_bytes();
+                    if let Some(blinding) = &entry.blinding {
+                        blindingshare.extend(Self::do_bytearray_xor(
+                            Self::blinding_hash(&thissaltedpasswordhash, x),
+                            blinding.clone(),
+                        )?);
+                    }
+                    blindinglist.push(blindingshare);
                 }
+                shareowners.insert(entry.sharenumber, username.clone());
             }
         }
-        self.shamirsecretobj
-            .clone()
-            .unwrap()
-            .recover_secretdata(sharelist);
+
+        // decode the secret, correcting for shares unprotected with a mistyped password
+        if sharelist.len() < self.threshold as usize {
+            return Err(PPHError {
+                kind: PPHErrorKind::RecoveryError,
+                msg: "not enough shares were supplied to recover the secret".to_string(),
+            });
+        }
+        let mut shamirsecretobj = ShamirSecret::with_field(self.threshold, None)?;
+        let invalidxs = match &self.commitments {
+            Some(commitments) => {
+                let blindingshares = match commitments.mode {
+                    VssMode::Pedersen => Some(blindinglist.clone()),
+                    VssMode::Feldman => None,
+                };
+                shamirsecretobj.recover_secretdata_verified(sharelist, blindingshares, commitments)?
+            }
+            None => shamirsecretobj.recover_secretdata_with_errors(sharelist)?,
+        };
+
+        // a mistyped password yields a different secret, so check it before accepting it
+        let secretdata = shamirsecretobj.secretdata.clone().unwrap();
+        if !utils::memcmp(&Self::secret_check(&secretdata), &self.secretcheck) {
+            return Err(PPHError {
+                kind: PPHErrorKind::RecoveryError,
+                msg: "recovered secret does not match the password file".to_string(),
+            });
+        }
+
+        // new accounts need blinding shares, so recover the blinding polynomials from the
+        // blinding shares that were verified along with their shares
+        if self.vss == Some(VssMode::Pedersen) {
+            let validblindings: Vec<Vec<u8>> = blindinglist
+                .into_iter()
+                .filter(|blindingshare| !invalidxs.contains(&F::from_bytes(blindingshare)))
+                .collect();
+            let mut blindingsecretobj = ShamirSecret::with_field(self.threshold, None)?;
+            blindingsecretobj.recover_secretdata_with_errors(validblindings)?;
+            self.blindingsecretobj = Some(blindingsecretobj);
+        }
+
+        // with the secret recovered, thresholdless accounts can be verified again
+        self.shamirsecretobj = Some(shamirsecretobj);
+        self.thresholdlesskey = Some(Self::derive_thresholdless_key(&secretdata));
         self.knownsecret = true;
-        Ok(())
+
+        let mut report = UnlockReport::default();
+        for x in invalidxs {
+            let sharenumber: Option<u16> = x.try_into().ok();
+            if let Some(owner) = sharenumber.and_then(|sharenumber| shareowners.get(&sharenumber)) {
+                if !report.invalidshares.contains(owner) {
+                    report.invalidshares.push(owner.clone());
+                }
+            }
+        }
+
+        // re-verify the logins accepted through partial verification now that the secret is known
+        for (username, tentativehash) in std::mem::take(&mut self.tentativelogins) {
+            if let Some(account) = self.accounts.get(&username) {
+                let verified = match tentativehash {
+                    Some(saltedpasswordhash) => self.verify_account(account, &saltedpasswordhash)?,
+                    None => false,
+                };
+                if !verified {
+                    report.rejectedlogins.push(username);
+                }
+            }
+        }
+        Ok(report)
     }
 }
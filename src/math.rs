@@ -1,8 +1,165 @@
 //! Define helpers functions and lookup tables for computing arithmetic
 //! over finite fields and polynomial interpolation.
 
+use serde::{Deserialize, Serialize};
+
+use sodiumoxide::randombytes;
+
+use crate::error::{PPHError, PPHErrorKind, PPHResult};
+use crate::ristretto::Scalar;
+
+use std::convert::{TryFrom, TryInto};
+use std::fmt::Debug;
+
+/// `FieldKind` identifies the finite field shares are computed over, as recorded in the password
+/// file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldKind {
+    #[default]
+    GF256,
+    GF65536,
+    Ristretto255,
+}
+
+/// `Field` is an element of a finite field that secrets are shared over. Share numbers are nonzero
+/// elements of the field, so larger fields allow more shares, and secrets are split into elements
+/// of `BYTES` bytes each.
+pub trait Field: Copy + Eq + Debug + Default + TryInto<u16> + TryFrom<u16> + 'static {
+    /// identifier of the field in the password file.
+    const KIND: FieldKind;
+
+    /// number of bytes taken by an element.
+    const BYTES: usize;
+
+    /// multiplicative identity of the field.
+    const ONE: Self;
+
+    fn add(self, other: Self) -> Self;
+    fn sub(self, other: Self) -> Self;
+    fn mul(self, other: Self) -> Self;
+    fn div(self, other: Self) -> PPHResult<Self>;
+
+    /// returns a uniformly random element.
+    fn random() -> Self;
+
+    /// reads an element from its first `BYTES` bytes, big-endian for binary fields.
+    fn from_bytes(bytes: &[u8]) -> Self;
+
+    /// writes an element as `BYTES` bytes, big-endian for binary fields.
+    fn to_bytes(self) -> Vec<u8>;
+}
+
+impl Field for u8 {
+    const KIND: FieldKind = FieldKind::GF256;
+    const BYTES: usize = 1;
+    const ONE: Self = 1;
+
+    fn add(self, other: Self) -> Self {
+        galois::gf256_add(self, other)
+    }
+
+    fn sub(self, other: Self) -> Self {
+        galois::gf256_sub(self, other)
+    }
+
+    fn mul(self, other: Self) -> Self {
+        galois::gf256_mul(self, other)
+    }
+
+    fn div(self, other: Self) -> PPHResult<Self> {
+        galois::gf256_div(self, other)
+    }
+
+    fn random() -> Self {
+        randombytes::randombytes(1)[0]
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        bytes[0]
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        vec![self]
+    }
+}
+
+impl Field for u16 {
+    const KIND: FieldKind = FieldKind::GF65536;
+    const BYTES: usize = 2;
+    const ONE: Self = 1;
+
+    fn add(self, other: Self) -> Self {
+        galois16::gf65536_add(self, other)
+    }
+
+    fn sub(self, other: Self) -> Self {
+        galois16::gf65536_sub(self, other)
+    }
+
+    fn mul(self, other: Self) -> Self {
+        galois16::gf65536_mul(self, other)
+    }
+
+    fn div(self, other: Self) -> PPHResult<Self> {
+        galois16::gf65536_div(self, other)
+    }
+
+    fn random() -> Self {
+        u16::from_bytes(&randombytes::randombytes(2))
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        u16::from_be_bytes([bytes[0], bytes[1]])
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+}
+
+/// the scalar field of Ristretto255, a prime field in which shares can be checked against public
+/// commitments. Elements take 32 little-endian bytes, and are reduced when read.
+impl Field for Scalar {
+    const KIND: FieldKind = FieldKind::Ristretto255;
+    const BYTES: usize = 32;
+    const ONE: Self = Scalar([
+        1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0,
+    ]);
+
+    fn add(self, other: Self) -> Self {
+        Scalar::add(&self, &other)
+    }
+
+    fn sub(self, other: Self) -> Self {
+        Scalar::sub(&self, &other)
+    }
+
+    fn mul(self, other: Self) -> Self {
+        Scalar::mul(&self, &other)
+    }
+
+    fn div(self, other: Self) -> PPHResult<Self> {
+        Ok(Scalar::mul(&self, &other.invert()?))
+    }
+
+    fn random() -> Self {
+        Scalar::random()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Scalar::reduce(&bytes[..Self::BYTES])
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        self.0.to_vec()
+    }
+}
 
 mod galois {
+    use super::{PPHError, PPHErrorKind, PPHResult};
+
     static GF256_EXP: [u8; 256] = [
         0x01, 0x03, 0x05, 0x0f, 0x11, 0x33, 0x55, 0xff, 0x1a, 0x2e, 0x72, 0x96, 0xa1, 0xf8, 0x13,
         0x35, 0x5f, 0xe1, 0x38, 0x48, 0xd8, 0x73, 0x95, 0xa4, 0xf7, 0x02, 0x06, 0x0a, 0x1e, 0x22,
@@ -60,94 +217,170 @@ mod galois {
         GF256_EXP[((GF256_LOG[a as usize] as u16 + GF256_LOG[b as usize] as u16) % 255) as usize]
     }
 
-    pub fn gf256_div(a: u8, b: u8) -> u8 {
-        if a == 0 {
-            return 0;
+    pub fn gf256_div(a: u8, b: u8) -> PPHResult<u8> {
+        if b == 0 {
+            return Err(PPHError {
+                kind: PPHErrorKind::FieldError,
+                msg: "division by zero".to_string(),
+            });
         }
 
-        if b == 0 {
-            panic!("Zero division!");
+        if a == 0 {
+            return Ok(0);
         }
 
         let a_log = GF256_LOG[a as usize] as i16;
         let b_log = GF256_LOG[b as usize] as i16;
 
-        let mut diff = a_log - b_log;
-        if diff < 0 {
-            diff += 256;
+        let diff = (a_log - b_log).rem_euclid(255);
+        Ok(GF256_EXP[diff as usize])
+    }
+}
+
+mod galois16 {
+    use super::{PPHError, PPHErrorKind, PPHResult};
+
+    use std::sync::OnceLock;
+
+    /// primitive polynomial x^16 + x^12 + x^3 + x + 1 defining the field, for which x generates
+    /// every nonzero element.
+    const GF65536_POLYNOMIAL: u32 = 0x1100b;
+
+    /// exponent and logarithm tables, computed on first use. The exponent table is doubled in
+    /// length so that sums of two logarithms can index it directly.
+    struct Tables {
+        exp: Vec<u16>,
+        log: Vec<u16>,
+    }
+
+    fn tables() -> &'static Tables {
+        static TABLES: OnceLock<Tables> = OnceLock::new();
+        TABLES.get_or_init(|| {
+            let mut exp: Vec<u16> = vec![0; 2 * 65535];
+            let mut log: Vec<u16> = vec![0; 65536];
+            let mut x: u32 = 1;
+            for i in 0..65535 {
+                exp[i] = x as u16;
+                exp[i + 65535] = x as u16;
+                log[x as usize] = i as u16;
+                x <<= 1;
+                if x & 0x10000 != 0 {
+                    x ^= GF65536_POLYNOMIAL;
+                }
+            }
+            Tables { exp, log }
+        })
+    }
+
+    pub fn gf65536_add(a: u16, b: u16) -> u16 {
+        a ^ b
+    }
+
+    pub fn gf65536_sub(a: u16, b: u16) -> u16 {
+        gf65536_add(a, b)
+    }
+
+    pub fn gf65536_mul(a: u16, b: u16) -> u16 {
+        if a == 0 || b == 0 {
+            return 0;
+        }
+        let tables = tables();
+        tables.exp[tables.log[a as usize] as usize + tables.log[b as usize] as usize]
+    }
+
+    pub fn gf65536_div(a: u16, b: u16) -> PPHResult<u16> {
+        if b == 0 {
+            return Err(PPHError {
+                kind: PPHErrorKind::FieldError,
+                msg: "division by zero".to_string(),
+            });
+        }
+
+        if a == 0 {
+            return Ok(0);
         }
-        GF256_EXP[(diff % 255) as usize]
+
+        let tables = tables();
+        let a_log = tables.log[a as usize] as usize;
+        let b_log = tables.log[b as usize] as usize;
+        Ok(tables.exp[a_log + 65535 - b_log])
     }
 }
 
 pub mod polynomial {
 
     use std::cmp::Ordering;
-    use super::galois;
+    use super::Field;
+    use super::{PPHError, PPHErrorKind, PPHResult};
 
-    pub fn compute_polynomial(x: u8, coefficient_bytes: Vec<u8>) -> u8 {
-        if x == 0 {
-            panic!("x cannot be equal to 0");
+    pub fn compute_polynomial<F: Field>(x: F, coefficient_bytes: Vec<F>) -> PPHResult<F> {
+        if x == F::default() {
+            return Err(PPHError {
+                kind: PPHErrorKind::FieldError,
+                msg: "x cannot be equal to 0".to_string(),
+            });
         }
 
-        let mut accumulator = 0;
-        let mut x_i = 1;
+        let mut accumulator = F::default();
+        let mut x_i = F::ONE;
 
         for coefficient in coefficient_bytes {
-            accumulator = galois::gf256_add(accumulator, galois::gf256_mul(coefficient, x_i));
-            x_i = galois::gf256_mul(x_i, x);
+            accumulator = accumulator.add(coefficient.mul(x_i));
+            x_i = x_i.mul(x);
         }
-        accumulator
+        Ok(accumulator)
     }
 
-    fn multiply_polynomials(a: Vec<u8>, b: Vec<u8>) -> Vec<u8> {
+    fn multiply_polynomials<F: Field>(a: Vec<F>, b: Vec<F>) -> Vec<F> {
         // Create a vector to store results after computation
-        let mut resultterms: Vec<u8> = vec![];
-        let mut termpadding: Vec<u8> = vec![];
+        let mut resultterms: Vec<F> = vec![];
+        let mut termpadding: Vec<F> = vec![];
 
         for bterm in b {
             let mut thisvalue = termpadding.clone();
             for aterm in a.clone() {
-                thisvalue.push(galois::gf256_mul(aterm, bterm));
+                thisvalue.push(aterm.mul(bterm));
             }
             resultterms = add_polynomials(resultterms, thisvalue);
-            termpadding.push(0);
+            termpadding.push(F::default());
         }
         resultterms
     }
 
-    fn add_polynomials(mut a: Vec<u8>, mut b: Vec<u8>) -> Vec<u8> {
-        let mut result: Vec<u8> = vec![];
+    fn add_polynomials<F: Field>(mut a: Vec<F>, mut b: Vec<F>) -> Vec<F> {
+        let mut result: Vec<F> = vec![];
 
         match a.len().cmp(&b.len()) {
             Ordering::Less => {
-                let mut c = vec![0; b.len() - a.len()];
+                let mut c = vec![F::default(); b.len() - a.len()];
                 a.append(&mut c);
             },
             Ordering::Greater => {
-                let mut c = vec![0; a.len() - b.len()];
+                let mut c = vec![F::default(); a.len() - b.len()];
                 b.append(&mut c);
             },
             Ordering::Equal => {},
         }
 
-        // TODO: error
-        assert!(a.len() == b.len());
-
         for position in 0..a.len() {
-            result.push(galois::gf256_add(a[position], b[position]));
+            result.push(a[position].add(b[position]));
         }
         result
     }
 
-    pub fn full_lagrange(xs: Vec<u8>, fxs: Vec<u8>) -> Vec<u8> {
+    pub fn full_lagrange<F: Field>(xs: Vec<F>, fxs: Vec<F>) -> PPHResult<Vec<F>> {
         // Takes a vector of x's and vector of f(x)'s and computes
         // the coefficients, plus the constant (secret data)
 
         // Makes sure that they are the same length!
-        assert!(xs.len() == fxs.len());
+        if xs.len() != fxs.len() {
+            return Err(PPHError {
+                kind: PPHErrorKind::FieldError,
+                msg: "x's and f(x)'s have different lengths".to_string(),
+            });
+        }
 
-        let mut returnedcoefficients: Vec<u8> = vec![];
+        let mut returnedcoefficients: Vec<F> = vec![];
 
         // How to compute:
         // l_0 =  (x - x_1) / (x_0 - x_1)   *   (x - x_2) / (x_0 - x_2) * ...
@@ -155,20 +388,157 @@ pub mod polynomial {
 
         for i in 0..fxs.len() {
             // Set current polynomial to compute
-            let mut this_polynomial: Vec<u8> = vec![1];
+            let mut this_polynomial: Vec<F> = vec![F::ONE];
             for j in 0..fxs.len() {
                 if i == j {
                     continue;
                 }
 
-                let denominator = galois::gf256_sub(xs[i], xs[j]);
-                let this_term = [galois::gf256_div(xs[j], denominator), galois::gf256_div(1, denominator)];
+                let denominator = xs[i].sub(xs[j]);
+                let this_term = [xs[j].div(denominator)?, F::ONE.div(denominator)?];
 
                 this_polynomial = multiply_polynomials(this_polynomial, this_term.to_vec());
             }
             this_polynomial = multiply_polynomials(this_polynomial, [fxs[i]].to_vec());
             returnedcoefficients = add_polynomials(returnedcoefficients, this_polynomial)
         }
-        returnedcoefficients
+        Ok(returnedcoefficients)
+    }
+
+    fn divide_polynomials<F: Field>(
+        mut numerator: Vec<F>,
+        denominator: Vec<F>,
+    ) -> PPHResult<(Vec<F>, Vec<F>)> {
+        // Long division, returning the quotient and remainder. The leading (last) coefficient of
+        // the denominator must be nonzero.
+        let denominator_degree = denominator.len() - 1;
+        let leading = denominator[denominator_degree];
+
+        if numerator.len() < denominator.len() {
+            return Ok((vec![F::default()], numerator));
+        }
+
+        let mut quotient: Vec<F> = vec![F::default(); numerator.len() - denominator_degree];
+        for position in (0..quotient.len()).rev() {
+            let factor = numerator[position + denominator_degree].div(leading)?;
+            quotient[position] = factor;
+            for (offset, dterm) in denominator.iter().enumerate() {
+                numerator[position + offset] = numerator[position + offset].sub(factor.mul(*dterm));
+            }
+        }
+        numerator.truncate(denominator_degree);
+        Ok((quotient, numerator))
+    }
+
+    fn solve_linear_system<F: Field>(mut rows: Vec<Vec<F>>, unknowns: usize) -> PPHResult<Vec<F>> {
+        // Gaussian elimination over an augmented matrix, where the last element of each row is the
+        // constant term. Free variables are set to 0, and inconsistent systems are an error.
+        let mut pivots: Vec<usize> = vec![];
+        let mut rank = 0;
+
+        for column in 0..unknowns {
+            let pivot = match (rank..rows.len()).find(|row| rows[*row][column] != F::default()) {
+                Some(pivot) => pivot,
+                None => continue,
+            };
+            rows.swap(rank, pivot);
+
+            let inverse = F::ONE.div(rows[rank][column])?;
+            for element in rows[rank].iter_mut() {
+                *element = element.mul(inverse);
+            }
+
+            let pivot_row = rows[rank].clone();
+            for (index, row) in rows.iter_mut().enumerate() {
+                let factor = row[column];
+                if index == rank || factor == F::default() {
+                    continue;
+                }
+                for (element, pivot_element) in row.iter_mut().zip(pivot_row.iter()) {
+                    *element = element.sub(factor.mul(*pivot_element));
+                }
+            }
+            pivots.push(column);
+            rank += 1;
+        }
+
+        // any remaining row with a nonzero constant term cannot be satisfied
+        if rows[rank..].iter().any(|row| row[unknowns] != F::default()) {
+            return Err(PPHError {
+                kind: PPHErrorKind::RecoveryError,
+                msg: "too many invalid points to decode".to_string(),
+            });
+        }
+
+        let mut solution: Vec<F> = vec![F::default(); unknowns];
+        for (row, column) in pivots.into_iter().enumerate() {
+            solution[column] = rows[row][unknowns];
+        }
+        Ok(solution)
+    }
+
+    pub fn berlekamp_welch<F: Field>(
+        xs: Vec<F>,
+        fxs: Vec<F>,
+        threshold: usize,
+    ) -> PPHResult<Vec<F>> {
+        // Takes a vector of x's and f(x)'s, some of which may be wrong, and computes the
+        // coefficients of the polynomial of degree below the threshold that passes through the
+        // rest, correcting up to (n - threshold) / 2 errors.
+
+        // Makes sure that they are the same length, and that there are enough points
+        if xs.len() != fxs.len() {
+            return Err(PPHError {
+                kind: PPHErrorKind::FieldError,
+                msg: "x's and f(x)'s have different lengths".to_string(),
+            });
+        }
+        if xs.len() < threshold {
+            return Err(PPHError {
+                kind: PPHErrorKind::FieldError,
+                msg: "fewer points than the threshold".to_string(),
+            });
+        }
+
+        // How to compute:
+        // find an error locator E (monic, of degree e) and Q = f * E (of degree below
+        // threshold + e) such that Q(x_i) = f(x_i) * E(x_i) for every point, then f = Q / E.
+        let errors = (xs.len() - threshold) / 2;
+        let qterms = threshold + errors;
+
+        let mut rows: Vec<Vec<F>> = vec![];
+        for (x, fx) in xs.iter().zip(fxs.iter()) {
+            let mut row: Vec<F> = vec![];
+            let mut x_i = F::ONE;
+            for _ in 0..qterms {
+                row.push(x_i);
+                x_i = x_i.mul(*x);
+            }
+
+            // the known terms of E move to the left-hand side, so they are negated, which only
+            // makes a difference outside of characteristic 2
+            let mut x_i = F::ONE;
+            for _ in 0..errors {
+                row.push(F::default().sub(fx.mul(x_i)));
+                x_i = x_i.mul(*x);
+            }
+            row.push(fx.mul(x_i));
+            rows.push(row);
+        }
+
+        let solution = solve_linear_system(rows, qterms + errors)?;
+        let q_polynomial = solution[..qterms].to_vec();
+        let mut e_polynomial = solution[qterms..].to_vec();
+        e_polynomial.push(F::ONE);
+
+        let (mut quotient, remainder) = divide_polynomials(q_polynomial, e_polynomial)?;
+        if remainder.iter().any(|term| *term != F::default()) {
+            return Err(PPHError {
+                kind: PPHErrorKind::RecoveryError,
+                msg: "too many invalid points to decode".to_string(),
+            });
+        }
+        quotient.resize(threshold, F::default());
+        Ok(quotient)
     }
 }
@@ -0,0 +1,48 @@
+//! Defines the application pepper, a secret key kept outside of the password file that is mixed
+//! into every salted password hash.
+
+use crate::error::{PPHError, PPHErrorKind, PPHResult};
+use crate::hasher::hmac;
+
+use std::env;
+use std::fs;
+
+/// `Pepper` is a secret key identified by an ID, which is recorded with each account hashed with
+/// it so that peppers can be rotated while older ones are still in use.
+#[derive(Clone)]
+pub struct Pepper {
+    pub id: String,
+    key: Vec<u8>,
+}
+
+impl Pepper {
+    /// creates a pepper from a key.
+    pub fn new(id: String, key: Vec<u8>) -> PPHResult<Pepper> {
+        if key.is_empty() {
+            return Err(PPHError {
+                kind: PPHErrorKind::PepperError,
+                msg: format!("pepper {} has an empty key", id),
+            });
+        }
+        Ok(Pepper { id, key })
+    }
+
+    /// reads the key of a pepper from an environment variable.
+    pub fn from_env(id: String, var: &str) -> PPHResult<Pepper> {
+        let key = env::var(var).map_err(|_| PPHError {
+            kind: PPHErrorKind::PepperError,
+            msg: format!("environment variable {} is not set", var),
+        })?;
+        Pepper::new(id, key.into_bytes())
+    }
+
+    /// reads the key of a pepper from a key file.
+    pub fn from_file(id: String, keyfile: &str) -> PPHResult<Pepper> {
+        Pepper::new(id, fs::read(keyfile)?)
+    }
+
+    /// mixes the pepper into a salted password hash, keeping its length.
+    pub fn mix(&self, saltedpasswordhash: &[u8]) -> Vec<u8> {
+        hmac(&self.key, saltedpasswordhash, saltedpasswordhash.len())
+    }
+}
@@ -0,0 +1,195 @@
+//! Defines the PHC-style string format used to import and export accounts as a single password
+//! string, so that they can be stored in one column of an existing user table.
+//!
+//! An account is written as `$pph$v=1$<params>$<salt>$<hash>`, where the parameters are the
+//! algorithm and cost of its hasher, its share numbers and its pepper ID, as in
+//! `alg=argon2id,ops=2,mem=67108864,shares=1.2,pepper=1`. The salt and the concatenated protected
+//! hashes of every share entry are encoded in unpadded base64, as is usual for PHC strings.
+//! Thresholdless accounts hold share 0.
+
+use crate::account::{Account, ShareEntry};
+use crate::error::{format_error, PPHResult};
+use crate::hasher::KdfParams;
+
+use std::collections::HashMap;
+
+/// identifier of the PolyPasswordHasher scheme in PHC strings.
+pub const PHC_ID: &str = "pph";
+
+/// current version of the PHC string format.
+pub const PHC_VERSION: u32 = 1;
+
+/// checks that a parameter value only uses the characters allowed in PHC strings.
+fn is_phc_value(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '/' || c == '+' || c == '.' || c == '-')
+}
+
+/// writes the hasher parameters of an account as PHC parameters.
+fn encode_kdf(kdf: &KdfParams) -> String {
+    match kdf {
+        KdfParams::Sha256 => "alg=sha256".to_string(),
+        KdfParams::Argon2id { opslimit, memlimit } => {
+            format!("alg=argon2id,ops={},mem={}", opslimit, memlimit)
+        }
+        KdfParams::Scrypt { opslimit, memlimit } => {
+            format!("alg=scrypt,ops={},mem={}", opslimit, memlimit)
+        }
+        KdfParams::Pbkdf2 { iterations } => format!("alg=pbkdf2,i={}", iterations),
+        KdfParams::Bcrypt { cost } => format!("alg=bcrypt,cost={}", cost),
+    }
+}
+
+/// reads the hasher parameters of an account from PHC parameters.
+fn decode_kdf(params: &HashMap<&str, &str>) -> PPHResult<KdfParams> {
+    let number = |name: &str| -> PPHResult<u64> {
+        match params.get(name) {
+            Some(value) => value
+                .parse::<u64>()
+                .map_err(|_| format_error(format!("invalid value for parameter {}", name))),
+            None => Err(format_error(format!("missing parameter {}", name))),
+        }
+    };
+    let small = |name: &str| -> PPHResult<u32> {
+        let value = number(name)?;
+        if value > u32::MAX as u64 {
+            return Err(format_error(format!("invalid value for parameter {}", name)));
+        }
+        Ok(value as u32)
+    };
+
+    match params.get("alg") {
+        Some(&"sha256") => Ok(KdfParams::Sha256),
+        Some(&"argon2id") => Ok(KdfParams::Argon2id {
+            opslimit: number("ops")?,
+            memlimit: number("mem")?,
+        }),
+        Some(&"scrypt") => Ok(KdfParams::Scrypt {
+            opslimit: number("ops")?,
+            memlimit: number("mem")?,
+        }),
+        Some(&"pbkdf2") => Ok(KdfParams::Pbkdf2 { iterations: small("i")? }),
+        Some(&"bcrypt") => Ok(KdfParams::Bcrypt { cost: small("cost")? }),
+        Some(alg) => Err(format_error(format!("unsupported algorithm {}", alg))),
+        None => Err(format_error("missing parameter alg".to_string())),
+    }
+}
+
+/// writes an account as a PHC string. Its ID and username are not part of the string.
+pub fn encode_account(account: &Account) -> PPHResult<String> {
+    if account.legacy.is_some() {
+        return Err(format_error(
+            "legacy accounts cannot be written as PHC strings until they are upgraded".to_string(),
+        ));
+    }
+    if account.entries.iter().any(|entry| entry.blinding.is_some()) {
+        return Err(format_error(
+            "accounts holding blinding shares cannot be written as PHC strings".to_string(),
+        ));
+    }
+
+    let mut params = encode_kdf(&account.kdf);
+
+    let sharenumbers: Vec<String> = account
+        .entries
+        .iter()
+        .map(|entry| entry.sharenumber.to_string())
+        .collect();
+    params.push_str(&format!(",shares={}", sharenumbers.join(".")));
+
+    if let Some(pepper) = &account.pepper {
+        if !is_phc_value(pepper) {
+            return Err(format_error(format!(
+                "pepper {} cannot be written in a PHC string",
+                pepper
+            )));
+        }
+        params.push_str(&format!(",pepper={}", pepper));
+    }
+
+    let hash: Vec<u8> = account
+        .entries
+        .iter()
+        .flat_map(|entry| entry.passhash.iter().cloned())
+        .collect();
+
+    Ok(format!(
+        "${}$v={}${}${}${}",
+        PHC_ID,
+        PHC_VERSION,
+        params,
+        base64::encode_config(&account.salt, base64::STANDARD_NO_PAD),
+        base64::encode_config(&hash, base64::STANDARD_NO_PAD)
+    ))
+}
+
+/// reads an account from a PHC string, giving it the ID and username it is stored under.
+pub fn decode_account(id: u64, username: String, phc: &str) -> PPHResult<Account> {
+    let fields: Vec<&str> = phc.split('$').collect();
+    if fields.len() != 6 || !fields[0].is_empty() || fields[1] != PHC_ID {
+        return Err(format_error("not a PolyPasswordHasher PHC string".to_string()));
+    }
+    if fields[2] != format!("v={}", PHC_VERSION) {
+        return Err(format_error(format!("unsupported PHC string version {}", fields[2])));
+    }
+
+    let mut params: HashMap<&str, &str> = HashMap::new();
+    for param in fields[3].split(',') {
+        match param.split_once('=') {
+            Some((name, value)) if is_phc_value(value) => {
+                if params.insert(name, value).is_some() {
+                    return Err(format_error(format!("duplicate parameter {}", name)));
+                }
+            }
+            _ => return Err(format_error(format!("malformed parameter {}", param))),
+        }
+    }
+
+    let kdf = decode_kdf(&params)?;
+    let pepper = params.get("pepper").map(|pepper| pepper.to_string());
+
+    let mut sharenumbers: Vec<u16> = vec![];
+    for sharenumber in params.get("shares").unwrap_or(&"").split('.') {
+        let sharenumber = sharenumber
+            .parse::<u16>()
+            .map_err(|_| format_error(format!("invalid share number {}", sharenumber)))?;
+        if sharenumbers.contains(&sharenumber) {
+            return Err(format_error(format!("duplicate share number {}", sharenumber)));
+        }
+        sharenumbers.push(sharenumber);
+    }
+    if sharenumbers.contains(&0) && sharenumbers.len() > 1 {
+        return Err(format_error("thresholdless accounts hold a single entry".to_string()));
+    }
+
+    let salt = base64::decode_config(fields[4], base64::STANDARD_NO_PAD)?;
+    let hash = base64::decode_config(fields[5], base64::STANDARD_NO_PAD)?;
+    if hash.is_empty() || hash.len() % sharenumbers.len() != 0 {
+        return Err(format_error("hash does not match the number of shares".to_string()));
+    }
+
+    // every entry protects the same salted hash, so they all have the same length
+    let entrylength = hash.len() / sharenumbers.len();
+    let entries: Vec<ShareEntry> = sharenumbers
+        .into_iter()
+        .zip(hash.chunks(entrylength))
+        .map(|(sharenumber, passhash)| ShareEntry {
+            sharenumber,
+            passhash: passhash.to_vec(),
+            blinding: None,
+        })
+        .collect();
+
+    Ok(Account {
+        id,
+        username,
+        salt,
+        kdf,
+        pepper,
+        legacy: None,
+        refreshed: false,
+        entries,
+    })
+}
@@ -0,0 +1,199 @@
+//! Defines safe wrappers over the Ristretto255 prime-order group provided by libsodium, whose
+//! scalar field is used to share secrets verifiably.
+
+use libsodium_sys as ffi;
+
+use crate::error::{PPHError, PPHErrorKind, PPHResult};
+
+use std::convert::TryFrom;
+
+/// length of encoded scalars and points.
+pub const SCALAR_BYTES: usize = 32;
+pub const POINT_BYTES: usize = 32;
+
+/// `Scalar` is an element of the prime field of order l = 2^252 +
+/// 27742317777372353535851937790883648493, in its canonical little-endian encoding.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Scalar(pub [u8; SCALAR_BYTES]);
+
+impl Scalar {
+    /// returns a uniformly random scalar.
+    pub fn random() -> Scalar {
+        let mut scalar = [0u8; SCALAR_BYTES];
+        unsafe { ffi::crypto_core_ristretto255_scalar_random(scalar.as_mut_ptr()) };
+        Scalar(scalar)
+    }
+
+    /// reduces up to 64 little-endian bytes modulo l.
+    pub fn reduce(bytes: &[u8]) -> Scalar {
+        let mut wide = [0u8; 2 * SCALAR_BYTES];
+        wide[..bytes.len()].copy_from_slice(bytes);
+        let mut scalar = [0u8; SCALAR_BYTES];
+        unsafe { ffi::crypto_core_ristretto255_scalar_reduce(scalar.as_mut_ptr(), wide.as_ptr()) };
+        Scalar(scalar)
+    }
+
+    /// reads a scalar from its canonical encoding, rejecting encodings of l or more.
+    pub fn from_canonical(bytes: &[u8]) -> Option<Scalar> {
+        if bytes.len() != SCALAR_BYTES {
+            return None;
+        }
+        let scalar = Scalar::reduce(bytes);
+        if scalar.0[..] != *bytes {
+            return None;
+        }
+        Some(scalar)
+    }
+
+    pub fn add(&self, other: &Scalar) -> Scalar {
+        let mut scalar = [0u8; SCALAR_BYTES];
+        unsafe {
+            ffi::crypto_core_ristretto255_scalar_add(
+                scalar.as_mut_ptr(),
+                self.0.as_ptr(),
+                other.0.as_ptr(),
+            )
+        };
+        Scalar(scalar)
+    }
+
+    pub fn sub(&self, other: &Scalar) -> Scalar {
+        let mut scalar = [0u8; SCALAR_BYTES];
+        unsafe {
+            ffi::crypto_core_ristretto255_scalar_sub(
+                scalar.as_mut_ptr(),
+                self.0.as_ptr(),
+                other.0.as_ptr(),
+            )
+        };
+        Scalar(scalar)
+    }
+
+    pub fn mul(&self, other: &Scalar) -> Scalar {
+        let mut scalar = [0u8; SCALAR_BYTES];
+        unsafe {
+            ffi::crypto_core_ristretto255_scalar_mul(
+                scalar.as_mut_ptr(),
+                self.0.as_ptr(),
+                other.0.as_ptr(),
+            )
+        };
+        Scalar(scalar)
+    }
+
+    pub fn invert(&self) -> PPHResult<Scalar> {
+        let mut scalar = [0u8; SCALAR_BYTES];
+        let result = unsafe {
+            ffi::crypto_core_ristretto255_scalar_invert(scalar.as_mut_ptr(), self.0.as_ptr())
+        };
+        if result != 0 {
+            return Err(PPHError {
+                kind: PPHErrorKind::FieldError,
+                msg: "division by zero".to_string(),
+            });
+        }
+        Ok(Scalar(scalar))
+    }
+}
+
+impl From<u16> for Scalar {
+    fn from(value: u16) -> Self {
+        let mut scalar = [0u8; SCALAR_BYTES];
+        scalar[..2].copy_from_slice(&value.to_le_bytes());
+        Scalar(scalar)
+    }
+}
+
+/// share numbers are the scalars below 2^16, so larger scalars are not share numbers.
+impl TryFrom<Scalar> for u16 {
+    type Error = PPHError;
+
+    fn try_from(scalar: Scalar) -> PPHResult<u16> {
+        if scalar.0[2..].iter().any(|byte| *byte != 0) {
+            return Err(PPHError {
+                kind: PPHErrorKind::FieldError,
+                msg: "scalar is too large for a share number".to_string(),
+            });
+        }
+        Ok(u16::from_le_bytes([scalar.0[0], scalar.0[1]]))
+    }
+}
+
+/// `Point` is an element of the Ristretto255 group in its canonical encoding. The identity is
+/// encoded as zeros.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Point(pub [u8; POINT_BYTES]);
+
+impl Point {
+    /// returns the fixed generator of the group multiplied by a scalar.
+    pub fn base(scalar: &Scalar) -> Point {
+        // libsodium reports an identity result as an error, but still writes it out as zeros
+        let mut point = [0u8; POINT_BYTES];
+        unsafe { ffi::crypto_scalarmult_ristretto255_base(point.as_mut_ptr(), scalar.0.as_ptr()) };
+        Point(point)
+    }
+
+    /// maps 64 bytes of uniformly random data, such as a hash, to a point with no known discrete
+    /// logarithm.
+    pub fn from_hash(hash: &[u8; 64]) -> Point {
+        let mut point = [0u8; POINT_BYTES];
+        unsafe { ffi::crypto_core_ristretto255_from_hash(point.as_mut_ptr(), hash.as_ptr()) };
+        Point(point)
+    }
+
+    /// multiplies the point by a scalar.
+    pub fn mul(&self, scalar: &Scalar) -> Point {
+        if *self == Point::default() {
+            return Point::default();
+        }
+        let mut point = [0u8; POINT_BYTES];
+        unsafe {
+            ffi::crypto_scalarmult_ristretto255(
+                point.as_mut_ptr(),
+                scalar.0.as_ptr(),
+                self.0.as_ptr(),
+            )
+        };
+        Point(point)
+    }
+
+    /// adds two points.
+    pub fn add(&self, other: &Point) -> Point {
+        if *self == Point::default() {
+            return *other;
+        }
+        if *other == Point::default() {
+            return *self;
+        }
+        let mut point = [0u8; POINT_BYTES];
+        unsafe {
+            ffi::crypto_core_ristretto255_add(point.as_mut_ptr(), self.0.as_ptr(), other.0.as_ptr())
+        };
+        Point(point)
+    }
+}
+
+impl TryFrom<&[u8]> for Point {
+    type Error = PPHError;
+
+    /// reads a point from its encoding, checking that it is a valid group element.
+    fn try_from(bytes: &[u8]) -> PPHResult<Point> {
+        let mut point = [0u8; POINT_BYTES];
+        if bytes.len() != POINT_BYTES {
+            return Err(PPHError {
+                kind: PPHErrorKind::FormatError,
+                msg: "point has an invalid length".to_string(),
+            });
+        }
+        point.copy_from_slice(bytes);
+        let identity = point == [0u8; POINT_BYTES];
+        if !identity && unsafe { ffi::crypto_core_ristretto255_is_valid_point(point.as_ptr()) } != 1
+        {
+            return Err(PPHError {
+                kind: PPHErrorKind::FormatError,
+                msg: "point is not a valid Ristretto255 element".to_string(),
+            });
+        }
+        Ok(Point(point))
+    }
+}
@@ -1,78 +1,160 @@
 //! Implementation of threshold secret sharing scheme with Lagrange polynomial interpolation.
 
-use sodiumoxide::randombytes;
-
-use crate::math::polynomial;
+use crate::error::{PPHError, PPHErrorKind, PPHResult};
+use crate::math::{polynomial, Field};
+use crate::vss::Commitments;
 
 /// `ShamirSecret` is a wrapper struct over parameters
 /// necessary in order to perform secret-sharing and
-/// polynomial interpolation.
+/// polynomial interpolation over the field `F`, which
+/// defaults to GF(2^8).
+///
+/// Shares are byte vectors made of the share number x
+/// followed by f(x) for every element of the secret,
+/// each written as the `F::BYTES` bytes of its encoding.
 #[derive(Debug, Clone)]
-pub struct ShamirSecret {
+pub struct ShamirSecret<F: Field = u8> {
     pub threshold: u8,
     pub secretdata: Option<Vec<u8>>,
-    pub coefficients: Vec<Vec<u8>>,
+    pub coefficients: Vec<Vec<F>>,
 }
 
 impl ShamirSecret {
-    /// `new()` generates a new ShamirSecret struct, with randomly generated coefficients. It
-    /// consumes a threshold, and an optional input buffer
-    pub fn new(threshold: u8, secretdata: Option<Vec<u8>>) -> ShamirSecret {
+    /// `new()` generates a new ShamirSecret struct over GF(2^8), with randomly generated
+    /// coefficients. It consumes a threshold, and an optional input buffer
+    pub fn new(threshold: u8, secretdata: Option<Vec<u8>>) -> PPHResult<ShamirSecret> {
+        ShamirSecret::with_field(threshold, secretdata)
+    }
+}
+
+impl<F: Field> ShamirSecret<F> {
+    /// `with_field()` generates a new ShamirSecret struct over the field `F`, as done by `new()`.
+    /// The input buffer must be a whole number of field elements long.
+    pub fn with_field(threshold: u8, secretdata: Option<Vec<u8>>) -> PPHResult<ShamirSecret<F>> {
+        if threshold < 1 {
+            return Err(PPHError {
+                kind: PPHErrorKind::ShareError,
+                msg: "threshold must be at least 1".to_string(),
+            });
+        }
+
         // initialize struct to hold raw coefficients
-        let mut coefficients: Vec<Vec<u8>> = vec![];
+        let mut coefficients: Vec<Vec<F>> = vec![];
 
         if let Some(data) = secretdata.clone() {
-            // initialize random bytes from threshold size
-            let rand_bytes = randombytes::randombytes((threshold - 1) as usize);
-
-            // Secret-sharing will be applied for each byte of the secret
-            for secretbyte in &data {
-                let mut coefficient: Vec<u8> = vec![*secretbyte];
-                for r in rand_bytes.iter() {
-                    coefficient.push(*r);
-                }
-                coefficients.push(coefficient);
+            if !data.len().is_multiple_of(F::BYTES) {
+                return Err(PPHError {
+                    kind: PPHErrorKind::ShareError,
+                    msg: format!("secret is not a multiple of {} bytes", F::BYTES),
+                });
+            }
+
+            // Secret-sharing will be applied for each element of the secret, each with its own
+            // random polynomial so that shares do not relate the elements to each other
+            for secretelement in data.chunks(F::BYTES) {
+                coefficients.push(ShamirSecret::random_polynomial(
+                    F::from_bytes(secretelement),
+                    threshold,
+                ));
             }
         }
 
-        ShamirSecret {
+        Ok(ShamirSecret {
             threshold,
             secretdata,
             coefficients
-        }
+        })
+    }
+
+    /// returns the coefficients of a random polynomial of degree threshold - 1 with the given
+    /// constant term.
+    fn random_polynomial(constant: F, threshold: u8) -> Vec<F> {
+        let mut coefficient: Vec<F> = vec![constant];
+        coefficient.extend((1..threshold).map(|_| F::random()));
+        coefficient
     }
 
-    pub fn is_valid_share(&self, share: Vec<u8>) -> bool {
+    /// `refresh()` replaces the polynomial of every element of the secret with a new random one
+    /// with the same constant term, so that the secret is kept but every share changes. A set of
+    /// shares mixing ones computed before and after the refresh does not recover the secret, but
+    /// the old and new shares at the same point together reveal the difference of the two
+    /// polynomials there, so whatever they protect must change as well.
+    pub fn refresh(&mut self) -> PPHResult<()> {
         if self.coefficients.is_empty() {
-            panic!("Coefficients were not initialized!");
+            return Err(PPHError {
+                kind: PPHErrorKind::ShareError,
+                msg: "coefficients were not initialized".to_string(),
+            });
         }
 
-        let x: u8 = share[0];
-        self.compute_share(x) == share
+        for coefficient in self.coefficients.iter_mut() {
+            *coefficient = ShamirSecret::random_polynomial(coefficient[0], self.threshold);
+        }
+        Ok(())
+    }
+
+    /// `reshare()` changes the threshold of the secret, replacing the polynomial of every element
+    /// with a new random one of the new degree as done by `refresh()`.
+    pub fn reshare(&mut self, threshold: u8) -> PPHResult<()> {
+        if threshold < 1 {
+            return Err(PPHError {
+                kind: PPHErrorKind::ShareError,
+                msg: "threshold must be at least 1".to_string(),
+            });
+        }
+        self.threshold = threshold;
+        self.refresh()
+    }
+
+    /// `blinding()` generates a random secret shared with the same threshold and length as this
+    /// one, whose polynomials blind Pedersen commitments to it.
+    pub fn blinding(&self) -> PPHResult<ShamirSecret<F>> {
+        let secretdata: Vec<u8> = (0..self.coefficients.len())
+            .flat_map(|_| F::random().to_bytes())
+            .collect();
+        ShamirSecret::with_field(self.threshold, Some(secretdata))
+    }
+
+    pub fn is_valid_share(&self, share: Vec<u8>) -> PPHResult<bool> {
+        if share.len() < F::BYTES {
+            return Err(PPHError {
+                kind: PPHErrorKind::ShareError,
+                msg: "share is empty".to_string(),
+            });
+        }
+
+        let x: F = F::from_bytes(&share);
+        Ok(self.compute_share(x)? == share)
     }
 
     /// computes shares and returns a tuple representing (x, f(x))
-    pub fn compute_share(&self, x: u8) -> Vec<u8> {
-        if x < 1 {
-            panic!("Cannot be smaller than 1 or greater than 255");
+    pub fn compute_share(&self, x: F) -> PPHResult<Vec<u8>> {
+        if x == F::default() {
+            return Err(PPHError {
+                kind: PPHErrorKind::ShareError,
+                msg: "share number cannot be smaller than 1".to_string(),
+            });
         }
         if self.coefficients.is_empty() {
-            panic!("Coefficients were not initialized!");
+            return Err(PPHError {
+                kind: PPHErrorKind::ShareError,
+                msg: "coefficients were not initialized".to_string(),
+            });
         }
 
-        let mut sharebytes: Vec<u8> = vec![];
+        let mut sharebytes: Vec<u8> = x.to_bytes();
 
         let coefficients = self.coefficients.clone();
         for coefficient in coefficients {
-            let share = polynomial::compute_polynomial(x, coefficient);
-            sharebytes.push(share);
+            let share = polynomial::compute_polynomial(x, coefficient)?;
+            sharebytes.extend(share.to_bytes());
         }
-        sharebytes.insert(0, x);
-        sharebytes
+        Ok(sharebytes)
     }
 
-    /// recover secretdata by passing vector with shares equal to threshold
-    pub fn recover_secretdata(&mut self, shares: Vec<Vec<u8>>) {
+    /// checks that there are at least threshold shares, all with distinct x values and of the same
+    /// length, and returns the shares with duplicates removed along with their x values.
+    fn check_shares(&self, shares: Vec<Vec<u8>>) -> PPHResult<(Vec<Vec<u8>>, Vec<F>)> {
         let mut newshares: Vec<Vec<u8>> = vec![];
 
         for share in shares.iter() {
@@ -81,43 +163,141 @@ impl ShamirSecret {
             }
         }
 
-        if self.threshold as usize > shares.len() {
-            panic!(
-                "Threshold: {} is smaller than the number of shares: {}",
-                self.threshold,
-                shares.len()
-            );
+        if self.threshold as usize > newshares.len() {
+            return Err(PPHError {
+                kind: PPHErrorKind::ShareError,
+                msg: format!(
+                    "Threshold: {} is greater than the number of shares: {}",
+                    self.threshold,
+                    newshares.len()
+                ),
+            });
         }
 
-        let mut xs: Vec<u8> = vec![];
-        for share in shares.iter() {
-            if xs.contains(&share[0]) {
-                panic!("Different shares with the same byte: {:?}", share[0]);
+        let mut xs: Vec<F> = vec![];
+        for share in newshares.iter() {
+            if share.len() < 2 * F::BYTES
+                || !share.len().is_multiple_of(F::BYTES)
+                || share.len() != newshares[0].len()
+            {
+                return Err(PPHError {
+                    kind: PPHErrorKind::ShareError,
+                    msg: "shares have different lengths".to_string(),
+                });
             }
-            if share.len() != shares[0].len() {
-                panic!("Shares have different lengths!");
+            let x = F::from_bytes(share);
+            if xs.contains(&x) {
+                return Err(PPHError {
+                    kind: PPHErrorKind::ShareError,
+                    msg: format!("different shares with the same x: {:?}", x),
+                });
             }
-            xs.push(share[0].clone());
+            xs.push(x);
         }
+        Ok((newshares, xs))
+    }
+
+    /// returns the f(x) values of every share for the element of the secret at the given index.
+    fn share_elements(shares: &[Vec<u8>], element: usize) -> Vec<F> {
+        let offset = (element + 1) * F::BYTES;
+        shares.iter().map(|share| F::from_bytes(&share[offset..])).collect()
+    }
 
-        let mut mycoefficients: Vec<u8> = vec![];
+    /// recover secretdata by passing vector with shares equal to threshold
+    pub fn recover_secretdata(&mut self, shares: Vec<Vec<u8>>) -> PPHResult<()> {
+        let (shares, xs) = self.check_shares(shares)?;
+
+        let mut mycoefficients: Vec<Vec<F>> = vec![];
         let mut mysecretdata: Vec<u8> = vec![];
 
-        let byte_walk = shares[0].len() - 1;
+        let element_walk = shares[0].len() / F::BYTES - 1;
+
+        for element_to_use in 0..element_walk {
+            let fxs = ShamirSecret::share_elements(&shares, element_to_use);
+
+            let result_polynomial = polynomial::full_lagrange(xs.clone(), fxs)?;
+            mysecretdata.extend(result_polynomial[0].to_bytes());
+            mycoefficients.push(result_polynomial);
+        }
+        self.coefficients = mycoefficients;
+        self.secretdata = Some(mysecretdata.to_vec());
+        Ok(())
+    }
+
+    /// recover secretdata from at least threshold shares, some of which may be invalid. Up to
+    /// (shares - threshold) / 2 invalid shares are corrected with Berlekamp-Welch decoding, and the
+    /// x values of the shares found to be invalid are returned.
+    pub fn recover_secretdata_with_errors(&mut self, shares: Vec<Vec<u8>>) -> PPHResult<Vec<F>> {
+        let (shares, xs) = self.check_shares(shares)?;
+
+        let mut mycoefficients: Vec<Vec<F>> = vec![];
+        let mut mysecretdata: Vec<u8> = vec![];
+        let mut invalidxs: Vec<F> = vec![];
 
-        for byte_to_use in 0..byte_walk {
-            let mut fxs: Vec<u8> = vec![];
-            for share in shares.clone() {
-                fxs.push(share[1..][byte_to_use].clone());
+        let element_walk = shares[0].len() / F::BYTES - 1;
+
+        for element_to_use in 0..element_walk {
+            let fxs = ShamirSecret::share_elements(&shares, element_to_use);
+
+            let result_polynomial =
+                polynomial::berlekamp_welch(xs.clone(), fxs.clone(), self.threshold as usize)?;
+
+            // shares that do not lie on the decoded polynomial are the invalid ones
+            for (x, fx) in xs.iter().zip(fxs.iter()) {
+                let valid = polynomial::compute_polynomial(*x, result_polynomial.clone())? == *fx;
+                if !valid && !invalidxs.contains(x) {
+                    invalidxs.push(*x);
+                }
             }
+            mysecretdata.extend(result_polynomial[0].to_bytes());
+            mycoefficients.push(result_polynomial);
+        }
+        self.coefficients = mycoefficients;
+        self.secretdata = Some(mysecretdata);
+        Ok(invalidxs)
+    }
+
+    /// recover secretdata from at least threshold shares, first setting aside the shares that do
+    /// not match the commitments to the polynomials, and then correcting for any remaining invalid
+    /// shares as done by `recover_secretdata_with_errors`. Pedersen commitments also need the
+    /// blinding share of every share, in the same order. The x values of the shares found to be
+    /// invalid are returned.
+    pub fn recover_secretdata_verified(
+        &mut self,
+        shares: Vec<Vec<u8>>,
+        blindingshares: Option<Vec<Vec<u8>>>,
+        commitments: &Commitments,
+    ) -> PPHResult<Vec<F>> {
+        let mut validshares: Vec<Vec<u8>> = vec![];
+        let mut invalidxs: Vec<F> = vec![];
+        for (i, share) in shares.into_iter().enumerate() {
+            let blindingshare = blindingshares
+                .as_ref()
+                .and_then(|blindingshares| blindingshares.get(i))
+                .map(|blindingshare| blindingshare.as_slice());
+            if commitments.verify_share(&share, blindingshare) {
+                validshares.push(share);
+            } else if share.len() >= F::BYTES && !invalidxs.contains(&F::from_bytes(&share)) {
+                invalidxs.push(F::from_bytes(&share));
+            }
+        }
+
+        if validshares.len() < self.threshold as usize {
+            return Err(PPHError {
+                kind: PPHErrorKind::RecoveryError,
+                msg: format!(
+                    "only {} shares match the commitments, but {} are needed",
+                    validshares.len(),
+                    self.threshold
+                ),
+            });
+        }
 
-            let result_polynomial = polynomial::full_lagrange(xs.clone(), fxs);
-            for coefficient in result_polynomial[..].iter() {
-                mycoefficients.push(*coefficient);
+        for x in self.recover_secretdata_with_errors(validshares)? {
+            if !invalidxs.contains(&x) {
+                invalidxs.push(x);
             }
-            mysecretdata.push(result_polynomial[0].clone());
         }
-        self.coefficients = vec![mycoefficients];
-        self.secretdata = Some(mysecretdata.to_vec())
+        Ok(invalidxs)
     }
 }
@@ -0,0 +1,220 @@
+//! Defines verifiable secret sharing, in which public commitments to the polynomials of a secret
+//! shared over the scalar field of Ristretto255 let shares be checked without knowing the secret.
+//!
+//! With Feldman commitments, each coefficient a of a polynomial is committed to as the point aB
+//! for the generator B of the group, and a share (x, y) is genuine if yB is the sum of the
+//! commitments to the coefficients a_j multiplied by x^j. Since aB is a deterministic function of
+//! a, the commitment to the constant term reveals the secret to anyone able to compute discrete
+//! logarithms.
+//!
+//! With Pedersen commitments, each polynomial f is paired with a random blinding polynomial g of
+//! the same degree, and the coefficients a_j and b_j are committed to as a_jB + b_jH, for a second
+//! generator H whose discrete logarithm to B is unknown. A share (x, f(x)) is then checked along
+//! with its blinding share (x, g(x)), and the commitments are hiding regardless of the computing
+//! power of whoever holds them.
+
+use serde::{Deserialize, Serialize};
+use sodiumoxide::crypto::hash::sha512;
+
+use crate::encoding::Encoding;
+use crate::error::{PPHError, PPHErrorKind, PPHResult};
+use crate::math::{Field, FieldKind};
+use crate::ristretto::{Point, Scalar, SCALAR_BYTES};
+use crate::secretshare::ShamirSecret;
+
+use std::convert::TryFrom;
+
+/// `VssMode` selects the commitments published for a verifiable secret.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VssMode {
+    Feldman,
+    Pedersen,
+}
+
+/// returns the second generator H used by Pedersen commitments, hashed to the group from a fixed
+/// label so that its discrete logarithm is not known to anyone.
+fn pedersen_generator() -> Point {
+    let sha512::Digest(hash) = sha512::hash(b"polypasswordhasher pedersen generator");
+    Point::from_hash(&hash)
+}
+
+/// checks that a secret is shared over the scalar field of Ristretto255 and has coefficients.
+fn check_secret<F: Field>(secret: &ShamirSecret<F>) -> PPHResult<()> {
+    if F::KIND != FieldKind::Ristretto255 {
+        return Err(PPHError {
+            kind: PPHErrorKind::ShareError,
+            msg: "verifiable secret sharing requires the Ristretto255 field".to_string(),
+        });
+    }
+    if secret.coefficients.is_empty() {
+        return Err(PPHError {
+            kind: PPHErrorKind::ShareError,
+            msg: "coefficients were not initialized".to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// reads the x value and the f(x) values of a share, which must be canonical scalars with x not
+/// zero, as many as the given number of elements.
+fn read_share(share: &[u8], elements: usize) -> Option<(Scalar, Vec<Scalar>)> {
+    if share.len() != (elements + 1) * SCALAR_BYTES {
+        return None;
+    }
+    let mut scalars = share.chunks(SCALAR_BYTES).map(Scalar::from_canonical);
+    let x = match scalars.next() {
+        Some(Some(x)) if x != Scalar::default() => x,
+        _ => return None,
+    };
+    let ys: Option<Vec<Scalar>> = scalars.collect();
+    Some((x, ys?))
+}
+
+/// `Commitments` holds a commitment to every coefficient of the polynomial shared for each
+/// element of a secret.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Commitments {
+    pub mode: VssMode,
+    pub points: Vec<Vec<Point>>,
+}
+
+impl Commitments {
+    /// computes Feldman commitments to a secret shared over the scalar field of Ristretto255.
+    pub fn feldman<F: Field>(secret: &ShamirSecret<F>) -> PPHResult<Commitments> {
+        check_secret(secret)?;
+
+        let points = secret.coefficients
+            .iter()
+            .map(|coefficient| {
+                coefficient
+                    .iter()
+                    .map(|a| Point::base(&Scalar::reduce(&a.to_bytes())))
+                    .collect()
+            })
+            .collect();
+        Ok(Commitments {
+            mode: VssMode::Feldman,
+            points,
+        })
+    }
+
+    /// computes Pedersen commitments to a secret shared over the scalar field of Ristretto255,
+    /// blinded by the polynomials of another secret of the same threshold and length, such as
+    /// the one returned by `ShamirSecret::blinding()`.
+    pub fn pedersen<F: Field>(
+        secret: &ShamirSecret<F>,
+        blinding: &ShamirSecret<F>,
+    ) -> PPHResult<Commitments> {
+        check_secret(secret)?;
+        check_secret(blinding)?;
+        let samedegree = secret.coefficients.len() == blinding.coefficients.len()
+            && secret.coefficients
+                .iter()
+                .zip(blinding.coefficients.iter())
+                .all(|(a, b)| a.len() == b.len());
+        if !samedegree {
+            return Err(PPHError {
+                kind: PPHErrorKind::ShareError,
+                msg: "blinding polynomials do not match the secret".to_string(),
+            });
+        }
+
+        let h = pedersen_generator();
+        let points = secret.coefficients
+            .iter()
+            .zip(blinding.coefficients.iter())
+            .map(|(coefficient, blindingcoefficient)| {
+                coefficient
+                    .iter()
+                    .zip(blindingcoefficient.iter())
+                    .map(|(a, b)| {
+                        let a = Scalar::reduce(&a.to_bytes());
+                        let b = Scalar::reduce(&b.to_bytes());
+                        Point::base(&a).add(&h.mul(&b))
+                    })
+                    .collect()
+            })
+            .collect();
+        Ok(Commitments {
+            mode: VssMode::Pedersen,
+            points,
+        })
+    }
+
+    /// checks a share against the commitments, along with its blinding share for Pedersen
+    /// commitments. Malformed shares, and blinding shares that are missing or for another x, are
+    /// not genuine.
+    pub fn verify_share(&self, share: &[u8], blindingshare: Option<&[u8]>) -> bool {
+        let (x, ys) = match read_share(share, self.points.len()) {
+            Some(share) => share,
+            None => return false,
+        };
+        let blindings = match (self.mode, blindingshare) {
+            (VssMode::Feldman, None) => None,
+            (VssMode::Pedersen, Some(blindingshare)) => {
+                match read_share(blindingshare, self.points.len()) {
+                    Some((blindingx, blindings)) if blindingx == x => Some(blindings),
+                    _ => return false,
+                }
+            }
+            _ => return false,
+        };
+        let h = pedersen_generator();
+
+        for (i, (points, y)) in self.points.iter().zip(ys.iter()).enumerate() {
+            let mut expected = Point::default();
+            let mut x_i = Scalar::from(1u16);
+            for point in points {
+                expected = expected.add(&point.mul(&x_i));
+                x_i = Scalar::mul(&x_i, &x);
+            }
+
+            let mut actual = Point::base(y);
+            if let Some(blindings) = &blindings {
+                actual = actual.add(&h.mul(&blindings[i]));
+            }
+            if actual != expected {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// encodes the commitments for storage in the password file.
+    pub fn encode(&self, encoding: Encoding) -> EncodedCommitments {
+        EncodedCommitments {
+            mode: self.mode,
+            points: self.points
+                .iter()
+                .map(|points| points.iter().map(|point| encoding.encode(&point.0)).collect())
+                .collect(),
+        }
+    }
+}
+
+/// `EncodedCommitments` is the representation of `Commitments` written to the password file, with
+/// its points encoded as text.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncodedCommitments {
+    pub mode: VssMode,
+    pub points: Vec<Vec<String>>,
+}
+
+impl EncodedCommitments {
+    /// decodes stored commitments, checking that every point is a valid group element.
+    pub fn decode(&self, encoding: Encoding) -> PPHResult<Commitments> {
+        let mut points: Vec<Vec<Point>> = vec![];
+        for encodedpoints in self.points.iter() {
+            let mut elementpoints: Vec<Point> = vec![];
+            for point in encodedpoints {
+                elementpoints.push(Point::try_from(encoding.decode(point)?.as_slice())?);
+            }
+            points.push(elementpoints);
+        }
+        Ok(Commitments {
+            mode: self.mode,
+            points,
+        })
+    }
+}
@@ -8,12 +8,1031 @@ extern crate polypasswordhasher;
 #[cfg(test)]
 mod tests {
 
+    use polypasswordhasher::builder::PolyPasswordHasherBuilder;
+    use polypasswordhasher::encoding::Encoding;
+    use polypasswordhasher::error::PPHErrorKind;
+    use polypasswordhasher::hasher::{
+        Argon2idHasher, BcryptHasher, KdfParams, PasswordHasher, Pbkdf2Hasher, ScryptHasher,
+        Sha256Hasher,
+    };
+    use polypasswordhasher::legacy;
+    use polypasswordhasher::pepper::Pepper;
+    use polypasswordhasher::phc;
+    use polypasswordhasher::ristretto::Scalar;
+    use polypasswordhasher::vss::VssMode;
     use polypasswordhasher::PolyPasswordHasher;
 
+    use std::env;
+
     #[test]
     fn test_create_pph() {
-        let mut pph = PolyPasswordHasher::new(2, None, None);
-        pph.create_account(String::from("admin"), String::from("correct horse"), 5);
-        pph.create_account(String::from("root"), String::from("battery staple"), 5);
+        let mut pph = PolyPasswordHasher::new(2, None, None).unwrap();
+        pph.create_account(String::from("admin"), String::from("correct horse"), 5).unwrap();
+        pph.create_account(String::from("root"), String::from("battery staple"), 5).unwrap();
+    }
+
+    #[test]
+    fn test_thresholdless_login() {
+        let mut pph = PolyPasswordHasher::new(2, None, None).unwrap();
+        pph.create_account(String::from("alice"), String::from("kitten"), 0).unwrap();
+
+        assert!(pph.is_valid_login(String::from("alice"), String::from("kitten")).unwrap());
+        assert!(!pph.is_valid_login(String::from("alice"), String::from("puppy")).unwrap());
+    }
+
+    #[test]
+    fn test_partial_verification() {
+        let passwordfile = env::temp_dir().join("test_partial_verification.json");
+        let passwordfile = passwordfile.to_str().unwrap().to_string();
+
+        let mut pph = PolyPasswordHasher::new(2, None, Some(2)).unwrap();
+        pph.create_account(String::from("admin"), String::from("correct horse"), 2).unwrap();
+        pph.create_account(String::from("alice"), String::from("kitten"), 0).unwrap();
+        pph.commit(passwordfile.clone()).unwrap();
+
+        // logins are tentatively checked against the partial bytes while locked
+        let mut pph = PolyPasswordHasher::new(2, Some(passwordfile), Some(2)).unwrap();
+        assert!(pph.is_valid_login(String::from("alice"), String::from("kitten")).unwrap());
+
+        // and fully re-verified once unlocked
+        let logindata = vec![(String::from("admin"), String::from("correct horse"))];
+        let report = pph.unlock_database(logindata).unwrap();
+        assert!(report.rejectedlogins.is_empty());
+        assert!(pph.is_valid_login(String::from("alice"), String::from("kitten")).unwrap());
+        assert!(pph.is_valid_login(String::from("admin"), String::from("correct horse")).unwrap());
+        assert!(!pph.is_valid_login(String::from("alice"), String::from("puppy")).unwrap());
+    }
+
+    #[test]
+    fn test_repeated_tentative_logins() {
+        let passwordfile = env::temp_dir().join("test_repeated_tentative_logins.json");
+        let passwordfile = passwordfile.to_str().unwrap().to_string();
+
+        let mut pph = PolyPasswordHasher::new(2, None, Some(1)).unwrap();
+        pph.set_hasher(Box::new(Sha256Hasher));
+        pph.create_account(String::from("admin"), String::from("correct horse"), 2).unwrap();
+        pph.create_account(String::from("alice"), String::from("kitten"), 0).unwrap();
+        pph.create_account(String::from("bob"), String::from("puppy"), 0).unwrap();
+        pph.commit(passwordfile.clone()).unwrap();
+
+        // repeated logins of a user are remembered once while locked
+        let mut pph = PolyPasswordHasher::new(2, Some(passwordfile), Some(1)).unwrap();
+        for _ in 0..1000 {
+            assert!(pph.is_valid_login(String::from("alice"), String::from("kitten")).unwrap());
+        }
+
+        // a wrong password that passes the single partial byte is caught after unlocking, even
+        // when the user also logs in with the right one
+        let wrongpassword = (0..100000)
+            .map(|guess| format!("guess {}", guess))
+            .find(|guess| pph.is_valid_login(String::from("bob"), guess.clone()).unwrap())
+            .unwrap();
+        for _ in 0..1000 {
+            assert!(pph.is_valid_login(String::from("bob"), String::from("puppy")).unwrap());
+            assert!(pph.is_valid_login(String::from("bob"), wrongpassword.clone()).unwrap());
+        }
+
+        let logindata = vec![(String::from("admin"), String::from("correct horse"))];
+        let report = pph.unlock_database(logindata).unwrap();
+        assert_eq!(report.rejectedlogins, vec![String::from("bob")]);
+        assert!(!pph.is_valid_login(String::from("bob"), wrongpassword).unwrap());
+    }
+
+    #[test]
+    fn test_locked_without_partial_bytes() {
+        let passwordfile = env::temp_dir().join("test_locked_without_partial_bytes.json");
+        let passwordfile = passwordfile.to_str().unwrap().to_string();
+
+        let mut pph = PolyPasswordHasher::new(1, None, None).unwrap();
+        pph.create_account(String::from("admin"), String::from("correct horse"), 1).unwrap();
+        pph.commit(passwordfile.clone()).unwrap();
+
+        let mut pph = PolyPasswordHasher::new(1, Some(passwordfile), None).unwrap();
+        assert!(pph.is_valid_login(String::from("admin"), String::from("correct horse")).is_err());
+    }
+
+    #[test]
+    fn test_remove_account() {
+        let mut pph = PolyPasswordHasher::new(2, None, None).unwrap();
+        pph.create_account(String::from("admin"), String::from("correct horse"), 2).unwrap();
+        pph.create_account(String::from("root"), String::from("battery staple"), 1).unwrap();
+        pph.create_account(String::from("alice"), String::from("kitten"), 0).unwrap();
+
+        // thresholdless accounts hold no shares, so they can always be removed
+        pph.remove_account(String::from("alice")).unwrap();
+        assert!(pph.is_valid_login(String::from("alice"), String::from("kitten")).is_err());
+
+        // removing the admin would leave a single share, below the threshold
+        assert!(pph.remove_account(String::from("admin")).is_err());
+        pph.remove_account(String::from("root")).unwrap();
+        assert!(pph.remove_account(String::from("root")).is_err());
+
+        // share numbers held by removed users are not reissued
+        pph.create_account(String::from("bob"), String::from("hunter2"), 1).unwrap();
+        assert!(pph.is_valid_login(String::from("bob"), String::from("hunter2")).unwrap());
+        assert!(pph.is_valid_login(String::from("admin"), String::from("correct horse")).unwrap());
+    }
+
+    #[test]
+    fn test_change_password() {
+        let mut pph = PolyPasswordHasher::new(2, None, None).unwrap();
+        pph.create_account(String::from("admin"), String::from("correct horse"), 2).unwrap();
+        pph.create_account(String::from("alice"), String::from("kitten"), 0).unwrap();
+
+        // the old password must be supplied correctly
+        assert!(pph.change_password(
+            String::from("alice"), String::from("puppy"), String::from("lion")
+        ).is_err());
+
+        pph.change_password(
+            String::from("alice"), String::from("kitten"), String::from("lion")
+        ).unwrap();
+        pph.change_password(
+            String::from("admin"), String::from("correct horse"), String::from("battery staple")
+        ).unwrap();
+
+        assert!(!pph.is_valid_login(String::from("alice"), String::from("kitten")).unwrap());
+        assert!(pph.is_valid_login(String::from("alice"), String::from("lion")).unwrap());
+        assert!(pph.is_valid_login(String::from("admin"), String::from("battery staple")).unwrap());
+    }
+
+    #[test]
+    fn test_multi_share_account() {
+        let passwordfile = env::temp_dir().join("test_multi_share_account.json");
+        let passwordfile = passwordfile.to_str().unwrap().to_string();
+
+        let mut pph = PolyPasswordHasher::new(3, None, None).unwrap();
+        pph.create_account(String::from("admin"), String::from("correct horse"), 3).unwrap();
+        pph.create_account(String::from("alice"), String::from("kitten"), 0).unwrap();
+        pph.commit(passwordfile.clone()).unwrap();
+
+        // a single user holding every share is enough to reach the threshold
+        let mut pph = PolyPasswordHasher::new(3, Some(passwordfile), None).unwrap();
+        let logindata = vec![(String::from("admin"), String::from("correct horse"))];
+        pph.unlock_database(logindata).unwrap();
+        assert!(pph.is_valid_login(String::from("alice"), String::from("kitten")).unwrap());
+    }
+
+    #[test]
+    fn test_encodings() {
+        for (name, encoding) in [("base64", Encoding::Base64), ("hex", Encoding::Hex)] {
+            let passwordfile = env::temp_dir().join(format!("test_encodings_{}.json", name));
+            let passwordfile = passwordfile.to_str().unwrap().to_string();
+
+            let mut pph = PolyPasswordHasher::new(1, None, None).unwrap();
+            pph.set_encoding(encoding);
+            pph.create_account(String::from("admin"), String::from("correct horse"), 1).unwrap();
+            pph.create_account(String::from("alice"), String::from("kitten"), 0).unwrap();
+            pph.commit(passwordfile.clone()).unwrap();
+
+            let mut pph = PolyPasswordHasher::new(1, Some(passwordfile), None).unwrap();
+            let logindata = vec![(String::from("admin"), String::from("correct horse"))];
+            pph.unlock_database(logindata).unwrap();
+            assert!(pph.is_valid_login(String::from("alice"), String::from("kitten")).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_incompatible_settings() {
+        let passwordfile = env::temp_dir().join("test_incompatible_settings.json");
+        let passwordfile = passwordfile.to_str().unwrap().to_string();
+
+        let mut pph = PolyPasswordHasher::new(2, None, Some(1)).unwrap();
+        pph.create_account(String::from("admin"), String::from("correct horse"), 2).unwrap();
+        pph.commit(passwordfile.clone()).unwrap();
+
+        // settings are read from the header, and must match those requested
+        assert!(PolyPasswordHasher::new(2, Some(passwordfile.clone()), Some(2)).is_err());
+        assert!(PolyPasswordHasher::new(2, Some(passwordfile.clone()), Some(1)).is_ok());
+
+        // except for the threshold, which is always the one recorded in the header
+        let mut pph = PolyPasswordHasher::new(3, Some(passwordfile), None).unwrap();
+        assert!(pph.is_valid_login(String::from("admin"), String::from("correct horse")).unwrap());
+        let logindata = vec![(String::from("admin"), String::from("correct horse"))];
+        pph.unlock_database(logindata).unwrap();
+    }
+
+    #[test]
+    fn test_malformed_accounts() {
+        let passwordfile = env::temp_dir().join("test_malformed_accounts.json");
+        let passwordfile = passwordfile.to_str().unwrap().to_string();
+
+        let mut pph = PolyPasswordHasher::new(2, None, None).unwrap();
+        pph.create_account(String::from("admin"), String::from("correct horse"), 2).unwrap();
+        pph.create_account(String::from("alice"), String::from("kitten"), 0).unwrap();
+        pph.commit(passwordfile.clone()).unwrap();
+        let contents = std::fs::read_to_string(&passwordfile).unwrap();
+        let contents: serde_json::Value = serde_json::from_str(&contents).unwrap();
+
+        // an account without any entry
+        let mut noentries = contents.clone();
+        noentries["accounts"]["alice"]["entries"] = serde_json::Value::Array(vec![]);
+
+        // a thresholdless account also holding a share
+        let mut mixedentries = contents.clone();
+        let shareentry = contents["accounts"]["admin"]["entries"][0].clone();
+        mixedentries["accounts"]["alice"]["entries"].as_array_mut().unwrap().push(shareentry);
+
+        for contents in [noentries, mixedentries] {
+            std::fs::write(&passwordfile, contents.to_string()).unwrap();
+            let error = PolyPasswordHasher::new(2, Some(passwordfile.clone()), None).err();
+            assert!(matches!(error.unwrap().kind, PPHErrorKind::FormatError));
+        }
+    }
+
+    #[test]
+    fn test_verified_unlock() {
+        let passwordfile = env::temp_dir().join("test_verified_unlock.json");
+        let passwordfile = passwordfile.to_str().unwrap().to_string();
+
+        let mut pph = PolyPasswordHasher::new(2, None, None).unwrap();
+        pph.create_account(String::from("admin"), String::from("correct horse"), 1).unwrap();
+        pph.create_account(String::from("root"), String::from("battery staple"), 1).unwrap();
+        pph.commit(passwordfile.clone()).unwrap();
+
+        // a mistyped password recovers the wrong secret, which is rejected
+        let mut pph = PolyPasswordHasher::new(2, Some(passwordfile), None).unwrap();
+        let logindata = vec![
+            (String::from("admin"), String::from("correct horse")),
+            (String::from("root"), String::from("battery stable")),
+        ];
+        let err = pph.unlock_database(logindata).unwrap_err();
+        assert!(matches!(err.kind, PPHErrorKind::RecoveryError));
+        assert!(pph.is_valid_login(String::from("admin"), String::from("correct horse")).is_err());
+
+        let logindata = vec![
+            (String::from("admin"), String::from("correct horse")),
+            (String::from("root"), String::from("battery staple")),
+        ];
+        pph.unlock_database(logindata).unwrap();
+        assert!(pph.is_valid_login(String::from("admin"), String::from("correct horse")).unwrap());
+    }
+
+    #[test]
+    fn test_error_tolerant_unlock() {
+        let passwordfile = env::temp_dir().join("test_error_tolerant_unlock.json");
+        let passwordfile = passwordfile.to_str().unwrap().to_string();
+
+        let mut pph = PolyPasswordHasher::new(2, None, None).unwrap();
+        pph.create_account(String::from("admin"), String::from("correct horse"), 1).unwrap();
+        pph.create_account(String::from("root"), String::from("battery staple"), 1).unwrap();
+        pph.create_account(String::from("bob"), String::from("hunter2"), 1).unwrap();
+        pph.create_account(String::from("carol"), String::from("letmein"), 1).unwrap();
+        pph.commit(passwordfile.clone()).unwrap();
+
+        // two of four shares being invalid is more than can be corrected for
+        let mut pph = PolyPasswordHasher::new(2, Some(passwordfile), None).unwrap();
+        let logindata = vec![
+            (String::from("admin"), String::from("correct horse")),
+            (String::from("root"), String::from("battery stable")),
+            (String::from("bob"), String::from("hunter3")),
+            (String::from("carol"), String::from("letmein")),
+        ];
+        let err = pph.unlock_database(logindata).unwrap_err();
+        assert!(matches!(err.kind, PPHErrorKind::RecoveryError));
+
+        // but a single one is, and its owner is reported
+        let logindata = vec![
+            (String::from("admin"), String::from("correct horse")),
+            (String::from("root"), String::from("battery stable")),
+            (String::from("bob"), String::from("hunter2")),
+            (String::from("carol"), String::from("letmein")),
+        ];
+        let report = pph.unlock_database(logindata).unwrap();
+        assert_eq!(report.invalidshares, vec![String::from("root")]);
+        assert!(pph.is_valid_login(String::from("admin"), String::from("correct horse")).unwrap());
+    }
+
+    #[test]
+    fn test_hashers() {
+        let salt = [7u8; 16];
+        let argon2id = Argon2idHasher::default();
+        let hash = argon2id.hash(&salt, "correct horse").unwrap();
+        assert_eq!(hash, argon2id.hash(&salt, "correct horse").unwrap());
+        assert_ne!(hash, argon2id.hash(&salt, "correct horsf").unwrap());
+        assert_ne!(hash, Sha256Hasher.hash(&salt, "correct horse").unwrap());
+
+        // the hasher is restored from the password file
+        let passwordfile = env::temp_dir().join("test_hashers.json");
+        let passwordfile = passwordfile.to_str().unwrap().to_string();
+
+        let mut pph = PolyPasswordHasher::new(2, None, Some(2)).unwrap();
+        pph.set_hasher(Box::new(Sha256Hasher));
+        pph.create_account(String::from("admin"), String::from("correct horse"), 2).unwrap();
+        pph.commit(passwordfile.clone()).unwrap();
+
+        let contents = std::fs::read_to_string(&passwordfile).unwrap();
+        assert!(contents.contains(r#""kdf":{"algorithm":"sha256"}"#));
+
+        let mut pph = PolyPasswordHasher::new(2, Some(passwordfile), Some(2)).unwrap();
+        assert!(pph.is_valid_login(String::from("admin"), String::from("correct horse")).unwrap());
+        assert_eq!(argon2id.params().hasher().params(), argon2id.params());
+        assert!(matches!(KdfParams::Sha256.hasher().params(), KdfParams::Sha256));
+    }
+
+    #[test]
+    fn test_scrypt_hasher() {
+        let scrypt = ScryptHasher::default();
+        let hash = scrypt.hash(&[7u8; 16], "correct horse").unwrap();
+        assert_eq!(hash, scrypt.hash(&[7u8; 16], "correct horse").unwrap());
+        assert_ne!(hash, Argon2idHasher::default().hash(&[7u8; 16], "correct horse").unwrap());
+
+        let passwordfile = env::temp_dir().join("test_scrypt_hasher.json");
+        let passwordfile = passwordfile.to_str().unwrap().to_string();
+
+        let mut pph = PolyPasswordHasher::new(2, None, None).unwrap();
+        pph.set_hasher(Box::new(scrypt));
+        pph.create_account(String::from("admin"), String::from("correct horse"), 2).unwrap();
+        pph.commit(passwordfile.clone()).unwrap();
+
+        let contents = std::fs::read_to_string(&passwordfile).unwrap();
+        assert!(contents.contains(r#""algorithm":"scrypt""#));
+
+        let mut pph = PolyPasswordHasher::new(2, Some(passwordfile), None).unwrap();
+        let logindata = vec![(String::from("admin"), String::from("correct horse"))];
+        pph.unlock_database(logindata).unwrap();
+        assert!(pph.is_valid_login(String::from("admin"), String::from("correct horse")).unwrap());
+    }
+
+    #[test]
+    fn test_pbkdf2_hasher() {
+        // well-known PBKDF2-HMAC-SHA256 test vectors
+        let hash = Pbkdf2Hasher { iterations: 1 }.hash(b"salt", "password").unwrap();
+        assert_eq!(
+            hex::encode(hash),
+            "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"
+        );
+        let hash = Pbkdf2Hasher { iterations: 4096 }.hash(b"salt", "password").unwrap();
+        assert_eq!(
+            hex::encode(hash),
+            "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"
+        );
+        assert!(Pbkdf2Hasher { iterations: 0 }.hash(b"salt", "password").is_err());
+
+        // longer hashes span several blocks
+        let hash = Pbkdf2Hasher { iterations: 1 }.derive(b"salt", "passwd", 64).unwrap();
+        assert_eq!(
+            hex::encode(hash),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc\
+             49ca9cccf179b645991664b39d77ef317c71b845b1e30bd509112041d3a19783"
+        );
+
+        // raising the iteration count keeps existing accounts valid
+        let passwordfile = env::temp_dir().join("test_pbkdf2_hasher.json");
+        let passwordfile = passwordfile.to_str().unwrap().to_string();
+
+        let mut pph = PolyPasswordHasher::new(2, None, None).unwrap();
+        pph.set_hasher(Box::new(Pbkdf2Hasher { iterations: 1000 }));
+        pph.create_account(String::from("admin"), String::from("correct horse"), 1).unwrap();
+        pph.set_hasher(Box::new(Pbkdf2Hasher { iterations: 2000 }));
+        pph.create_account(String::from("root"), String::from("battery staple"), 1).unwrap();
+        pph.commit(passwordfile.clone()).unwrap();
+
+        let contents = std::fs::read_to_string(&passwordfile).unwrap();
+        assert!(contents.contains(r#""iterations":1000"#));
+        assert!(contents.contains(r#""kdf":{"algorithm":"pbkdf2","iterations":2000}"#));
+
+        let mut pph = PolyPasswordHasher::new(2, Some(passwordfile), None).unwrap();
+        let logindata = vec![
+            (String::from("admin"), String::from("correct horse")),
+            (String::from("root"), String::from("battery staple")),
+        ];
+        pph.unlock_database(logindata).unwrap();
+        assert!(pph.is_valid_login(String::from("admin"), String::from("correct horse")).unwrap());
+        assert!(pph.is_valid_login(String::from("root"), String::from("battery staple")).unwrap());
+    }
+
+    #[test]
+    fn test_bcrypt_hasher() {
+        let bcrypt = BcryptHasher { cost: 4 };
+        let hash = bcrypt.hash(&[7u8; 16], "correct horse").unwrap();
+        assert_eq!(hash.len(), 32);
+        assert_eq!(hash, bcrypt.hash(&[7u8; 16], "correct horse").unwrap());
+        assert_ne!(hash, bcrypt.hash(&[8u8; 16], "correct horse").unwrap());
+        assert!(BcryptHasher { cost: 3 }.hash(&[7u8; 16], "correct horse").is_err());
+
+        let mut pph = PolyPasswordHasher::new(2, None, None).unwrap();
+        pph.set_hasher(Box::new(bcrypt));
+        pph.create_account(String::from("admin"), String::from("correct horse"), 2).unwrap();
+        pph.create_account(String::from("alice"), String::from("kitten"), 0).unwrap();
+        assert!(pph.is_valid_login(String::from("admin"), String::from("correct horse")).unwrap());
+        assert!(pph.is_valid_login(String::from("alice"), String::from("kitten")).unwrap());
+        assert!(!pph.is_valid_login(String::from("alice"), String::from("puppy")).unwrap());
+    }
+
+    #[test]
+    fn test_rehash_on_login() {
+        let passwordfile = env::temp_dir().join("test_rehash_on_login.json");
+        let passwordfile = passwordfile.to_str().unwrap().to_string();
+
+        let mut pph = PolyPasswordHasher::new(2, None, None).unwrap();
+        pph.set_hasher(Box::new(Pbkdf2Hasher { iterations: 1000 }));
+        pph.create_account(String::from("admin"), String::from("correct horse"), 1).unwrap();
+        pph.create_account(String::from("root"), String::from("battery staple"), 1).unwrap();
+
+        // a successful login moves the account to the current parameters, but a failed one does not
+        pph.set_hasher(Box::new(Pbkdf2Hasher { iterations: 2000 }));
+        assert!(pph.is_valid_login(String::from("admin"), String::from("correct horse")).unwrap());
+        assert!(!pph.is_valid_login(String::from("root"), String::from("battery stable")).unwrap());
+        pph.commit(passwordfile.clone()).unwrap();
+
+        let contents = std::fs::read_to_string(&passwordfile).unwrap();
+        assert_eq!(contents.matches(r#""iterations":1000"#).count(), 1);
+        assert_eq!(contents.matches(r#""iterations":2000"#).count(), 2);
+
+        // the re-hashed account still holds its share
+        let mut pph = PolyPasswordHasher::new(2, Some(passwordfile), None).unwrap();
+        let logindata = vec![
+            (String::from("admin"), String::from("correct horse")),
+            (String::from("root"), String::from("battery staple")),
+        ];
+        pph.unlock_database(logindata).unwrap();
+        assert!(pph.is_valid_login(String::from("admin"), String::from("correct horse")).unwrap());
+    }
+
+    #[test]
+    fn test_pepper() {
+        let passwordfile = env::temp_dir().join("test_pepper.json");
+        let passwordfile = passwordfile.to_str().unwrap().to_string();
+        let keyfile = env::temp_dir().join("test_pepper.key");
+        std::fs::write(&keyfile, b"second pepper key").unwrap();
+        let keyfile = keyfile.to_str().unwrap().to_string();
+
+        let mut pph = PolyPasswordHasher::new(2, None, Some(2)).unwrap();
+        pph.set_hasher(Box::new(Sha256Hasher));
+        pph.add_pepper(Pepper::new(String::from("1"), b"first pepper key".to_vec()).unwrap());
+        pph.create_account(String::from("admin"), String::from("correct horse"), 1).unwrap();
+        pph.create_account(String::from("root"), String::from("battery staple"), 1).unwrap();
+        pph.create_account(String::from("alice"), String::from("kitten"), 0).unwrap();
+
+        // rotating the pepper moves accounts over as they log in
+        pph.add_pepper(Pepper::from_file(String::from("2"), &keyfile).unwrap());
+        assert!(pph.is_valid_login(String::from("admin"), String::from("correct horse")).unwrap());
+        assert!(pph.remove_pepper("1").is_err());
+        pph.commit(passwordfile.clone()).unwrap();
+
+        let contents = std::fs::read_to_string(&passwordfile).unwrap();
+        assert!(!contents.contains("pepper key"));
+        assert_eq!(contents.matches(r#""pepper":"1""#).count(), 2);
+        assert_eq!(contents.matches(r#""pepper":"2""#).count(), 1);
+
+        // without the peppers, even partial verification fails
+        let mut pph = PolyPasswordHasher::new(2, Some(passwordfile.clone()), Some(2)).unwrap();
+        let err = pph.is_valid_login(String::from("alice"), String::from("kitten")).unwrap_err();
+        assert!(matches!(err.kind, PPHErrorKind::PepperError));
+
+        let mut pph = PolyPasswordHasher::new(2, Some(passwordfile), Some(2)).unwrap();
+        pph.add_pepper(Pepper::new(String::from("1"), b"first pepper key".to_vec()).unwrap());
+        env::set_var("TEST_PEPPER_KEY", "second pepper key");
+        pph.add_pepper(Pepper::from_env(String::from("2"), "TEST_PEPPER_KEY").unwrap());
+        let logindata = vec![
+            (String::from("admin"), String::from("correct horse")),
+            (String::from("root"), String::from("battery staple")),
+        ];
+        pph.unlock_database(logindata).unwrap();
+        assert!(pph.is_valid_login(String::from("alice"), String::from("kitten")).unwrap());
+        assert!(pph.is_valid_login(String::from("root"), String::from("battery staple")).unwrap());
+        pph.remove_pepper("1").unwrap();
+        assert!(pph.is_valid_login(String::from("admin"), String::from("correct horse")).unwrap());
+        assert!(Pepper::new(String::from("3"), vec![]).is_err());
+    }
+
+    #[test]
+    fn test_phc_strings() {
+        let mut pph = PolyPasswordHasher::new(2, None, Some(2)).unwrap();
+        pph.set_hasher(Box::new(Pbkdf2Hasher { iterations: 1000 }));
+        pph.add_pepper(Pepper::new(String::from("1"), b"pepper key".to_vec()).unwrap());
+        pph.create_account(String::from("admin"), String::from("correct horse"), 2).unwrap();
+        pph.create_account(String::from("alice"), String::from("kitten"), 0).unwrap();
+
+        let admin = pph.export_account(String::from("admin")).unwrap();
+        assert!(admin.starts_with("$pph$v=1$alg=pbkdf2,i=1000,shares=1.2,pepper=1$"));
+        let account = phc::decode_account(0, String::from("admin"), &admin).unwrap();
+        assert_eq!(account.sharenumbers(), vec![1, 2]);
+        assert_eq!(phc::encode_account(&account).unwrap(), admin);
+
+        // the shares of an account cannot be imported twice
+        let err = pph.import_account(String::from("admin2"), &admin).unwrap_err();
+        assert!(matches!(err.kind, PPHErrorKind::ShardError));
+
+        let alice = pph.export_account(String::from("alice")).unwrap();
+        assert!(alice.contains(",shares=0,"));
+        pph.import_account(String::from("alice2"), &alice).unwrap();
+        assert!(pph.is_valid_login(String::from("alice2"), String::from("kitten")).unwrap());
+
+        let malformed = [
+            "$argon2id$v=19$m=65536,t=2,p=1$c2FsdA$aGFzaA",
+            "$pph$v=2$alg=sha256,shares=3$c2FsdA$aGFzaA",
+            "$pph$v=1$alg=md5,shares=3$c2FsdA$aGFzaA",
+            "$pph$v=1$alg=sha256,shares=3.3$c2FsdA$aGFzaA",
+            "$pph$v=1$alg=sha256,shares=3$c2FsdA$aGFzaA",
+        ];
+        for passwordstring in malformed.iter() {
+            let err = pph.import_account(String::from("bob"), passwordstring).unwrap_err();
+            assert!(matches!(err.kind, PPHErrorKind::FormatError));
+        }
+    }
+
+    #[test]
+    fn test_legacy_hashes() {
+        let vectors = [
+            (
+                "Hello world!",
+                "$6$saltstring$svn8UoSVapNtMuq1ukKS4tPQd8iKwSMHWjl/O817G3uBnIFNjnQJuesI68u4OTLiBFd\
+                 cbYEdFCoEOfaS35inz1",
+            ),
+            (
+                "Hello world!",
+                "$6$rounds=10000$saltstringsaltst$OW1/O6BYHV6BcXZu8QVeXbDWra3Oeqh0sbHbbMCVNSnCM/Urj\
+                 mM0Dp8vOuZeHBy/YTBmSK6H9qs/y3RnOaw5v.",
+            ),
+            ("x", "$2b$04$aaaaaaaaaaaaaaaaaaaaaOm2jmFIGeWCxfiTbi1uH3fjYm7p.FTQS"),
+            ("myPassword", "$apr1$r31salt0$tCl.m.9pyD8WKwsBQsglN0"),
+        ];
+        for (password, legacyhash) in vectors.iter() {
+            let (setting, hash) = legacy::split(legacyhash).unwrap();
+            assert_eq!(legacy::compute(&setting, password).unwrap(), hash);
+            assert_ne!(legacy::compute(&setting, "wrong password").unwrap(), hash);
+        }
+        assert!(legacy::split("$1$md5salt$hash").is_err());
+        assert!(legacy::split("$apr1$r31salt0$tCl.m.9pyD8WKwsBQsg").is_err());
+    }
+
+    #[test]
+    fn test_migrate_account() {
+        let passwordfile = env::temp_dir().join("test_migrate_account.json");
+        let passwordfile = passwordfile.to_str().unwrap().to_string();
+
+        let mut pph = PolyPasswordHasher::new(2, None, Some(2)).unwrap();
+        pph.set_hasher(Box::new(Sha256Hasher));
+        let admin = "$6$adminsalt$.Ka7tDa7KcrsU2ng.Orefrjrapjn27ATYfG2/OoyO.xI.EaHeZ/c7kpE5JHikP/\
+                     sCXZdA6NPMFeZyFquMo9Mo/";
+        let root = "$2b$05$LhayLxezLhK1LhWvKxCyLOrCb/qji959IUfJvcnYAoEWme/zTEB6q";
+        let bob = "$apr1$bobsalt$4RXTCGFa0eh7TuoTK8JP1.";
+        pph.migrate_account(String::from("admin"), String::from(admin), 1).unwrap();
+        pph.migrate_account(String::from("root"), String::from(root), 1).unwrap();
+        pph.migrate_account(String::from("bob"), String::from(bob), 0).unwrap();
+        assert!(pph.export_account(String::from("bob")).is_err());
+        pph.commit(passwordfile.clone()).unwrap();
+
+        // legacy accounts are verified from their passwords, and can unlock the database
+        let mut pph = PolyPasswordHasher::new(2, Some(passwordfile.clone()), Some(2)).unwrap();
+        assert!(pph.is_valid_login(String::from("bob"), String::from("hunter2")).unwrap());
+        let logindata = vec![
+            (String::from("admin"), String::from("correct horse")),
+            (String::from("root"), String::from("battery staple")),
+        ];
+        let report = pph.unlock_database(logindata).unwrap();
+        assert!(report.rejectedlogins.is_empty());
+        assert!(!pph.is_valid_login(String::from("bob"), String::from("hunter3")).unwrap());
+
+        // and are upgraded to native accounts on their next login
+        assert!(pph.is_valid_login(String::from("bob"), String::from("hunter2")).unwrap());
+        assert!(pph.is_valid_login(String::from("admin"), String::from("correct horse")).unwrap());
+        pph.commit(passwordfile.clone()).unwrap();
+
+        let contents = std::fs::read_to_string(&passwordfile).unwrap();
+        assert!(!contents.contains("$6$") && !contents.contains("$apr1$"));
+        assert!(contents.contains("$2b$"));
+
+        let mut pph = PolyPasswordHasher::new(2, Some(passwordfile), Some(2)).unwrap();
+        let logindata = vec![
+            (String::from("admin"), String::from("correct horse")),
+            (String::from("root"), String::from("battery staple")),
+        ];
+        pph.unlock_database(logindata).unwrap();
+        assert!(pph.is_valid_login(String::from("bob"), String::from("hunter2")).unwrap());
+    }
+
+    #[test]
+    fn test_digest_sizes() {
+        let hashers: Vec<Box<dyn PasswordHasher>> = vec![
+            Box::new(Sha256Hasher),
+            Box::new(Argon2idHasher::default()),
+            Box::new(Pbkdf2Hasher { iterations: 1000 }),
+            Box::new(BcryptHasher { cost: 4 }),
+        ];
+        for hasher in hashers.iter() {
+            for length in [32, 48, 64] {
+                let hash = hasher.derive(&[7u8; 16], "correct horse", length).unwrap();
+                assert_eq!(hash.len(), length);
+            }
+            assert!(hasher.derive(&[7u8; 16], "correct horse", 40).is_err());
+        }
+
+        let passwordfile = env::temp_dir().join("test_digest_sizes.json");
+        let passwordfile = passwordfile.to_str().unwrap().to_string();
+
+        for digestsize in [48, 64] {
+            let mut pph = PolyPasswordHasher::new(2, None, Some(2)).unwrap();
+            pph.set_hasher(Box::new(Sha256Hasher));
+            pph.set_digest_size(digestsize).unwrap();
+            pph.add_pepper(Pepper::new(String::from("1"), b"pepper".to_vec()).unwrap());
+            pph.create_account(String::from("admin"), String::from("correct horse"), 2).unwrap();
+            pph.create_account(String::from("guest"), String::from("kitten"), 0).unwrap();
+            assert!(pph.set_digest_size(32).is_err());
+            assert!(pph.is_valid_login(String::from("guest"), String::from("kitten")).unwrap());
+            pph.commit(passwordfile.clone()).unwrap();
+
+            let mut pph = PolyPasswordHasher::new(2, Some(passwordfile.clone()), None).unwrap();
+            pph.add_pepper(Pepper::new(String::from("1"), b"pepper".to_vec()).unwrap());
+            let logindata = vec![(String::from("admin"), String::from("correct horse"))];
+            pph.unlock_database(logindata).unwrap();
+            assert!(pph.is_valid_login(String::from("guest"), String::from("kitten")).unwrap());
+            assert!(!pph.is_valid_login(String::from("guest"), String::from("kitteN")).unwrap());
+        }
+
+        let mut pph = PolyPasswordHasher::new(2, None, None).unwrap();
+        assert!(pph.set_digest_size(40).is_err());
+
+        // digests over Ristretto255 must be a whole number of 32-byte scalars
+        let mut pph = PolyPasswordHasherBuilder::new(2)
+            .field::<Scalar>()
+            .hasher(Box::new(Sha256Hasher))
+            .build()
+            .unwrap();
+        let err = pph.set_digest_size(48).unwrap_err();
+        assert!(matches!(err.kind, PPHErrorKind::ConfigError));
+        pph.set_digest_size(64).unwrap();
+        pph.create_account(String::from("admin"), String::from("correct horse"), 1).unwrap();
+    }
+
+    #[test]
+    fn test_builder() {
+        let passwordfile = env::temp_dir().join("test_builder.json");
+        let passwordfile = passwordfile.to_str().unwrap().to_string();
+
+        // invalid combinations of settings are rejected
+        let invalid = [
+            PolyPasswordHasherBuilder::new(0),
+            PolyPasswordHasherBuilder::new(2).saltsize(4),
+            PolyPasswordHasherBuilder::new(2).saltsize(128),
+            PolyPasswordHasherBuilder::new(2).digestsize(40),
+            PolyPasswordHasherBuilder::new(2).partialbytes(32),
+            PolyPasswordHasherBuilder::new(2).digestsize(48).partialbytes(48),
+        ];
+        for builder in invalid {
+            let error = builder.build().err().unwrap();
+            assert!(matches!(error.kind, PPHErrorKind::ConfigError));
+        }
+
+        let mut pph = PolyPasswordHasherBuilder::new(2)
+            .saltsize(32)
+            .partialbytes(2)
+            .digestsize(64)
+            .hasher(Box::new(Sha256Hasher))
+            .pepper(Pepper::new(String::from("1"), b"pepper".to_vec()).unwrap())
+            .encoding(Encoding::Hex)
+            .build()
+            .unwrap();
+        pph.create_account(String::from("admin"), String::from("correct horse"), 2).unwrap();
+        pph.commit(passwordfile.clone()).unwrap();
+
+        let contents = std::fs::read_to_string(&passwordfile).unwrap();
+        assert!(contents.contains(r#""saltsize":32"#));
+        assert!(contents.contains(r#""digestsize":64"#));
+        assert!(contents.contains(r#""algorithm":"sha256""#));
+
+        // settings given when loading must match the header
+        let builder = PolyPasswordHasherBuilder::new(2).passwordfile(passwordfile.clone());
+        assert!(builder.saltsize(16).build().is_err());
+        let builder = PolyPasswordHasherBuilder::new(2).passwordfile(passwordfile.clone());
+        assert!(builder.digestsize(32).build().is_err());
+
+        let mut pph = PolyPasswordHasherBuilder::new(2)
+            .passwordfile(passwordfile)
+            .digestsize(64)
+            .pepper(Pepper::new(String::from("1"), b"pepper".to_vec()).unwrap())
+            .build()
+            .unwrap();
+        assert!(pph.is_valid_login(String::from("admin"), String::from("correct horse")).unwrap());
+        let logindata = vec![(String::from("admin"), String::from("correct horse"))];
+        pph.unlock_database(logindata).unwrap();
+        assert!(pph.is_valid_login(String::from("admin"), String::from("correct horse")).unwrap());
+    }
+
+    #[test]
+    fn test_large_field() {
+        let passwordfile = env::temp_dir().join("test_large_field.json");
+        let passwordfile = passwordfile.to_str().unwrap().to_string();
+
+        // GF(2^8) runs out of share numbers after 255
+        let mut pph = PolyPasswordHasherBuilder::new(2)
+            .hasher(Box::new(Sha256Hasher))
+            .build()
+            .unwrap();
+        pph.create_account(String::from("root"), String::from("correct horse"), 200).unwrap();
+        let err = pph.create_account(String::from("admin"), String::from("kitten"), 100);
+        assert!(matches!(err.unwrap_err().kind, PPHErrorKind::ShardError));
+
+        let mut pph = PolyPasswordHasherBuilder::new(2)
+            .field::<u16>()
+            .hasher(Box::new(Sha256Hasher))
+            .build()
+            .unwrap();
+        pph.create_account(String::from("root"), String::from("correct horse"), 200).unwrap();
+        pph.create_account(String::from("admin"), String::from("kitten"), 100).unwrap();
+        pph.create_account(String::from("alice"), String::from("puppy"), 2).unwrap();
+        assert!(pph.export_account(String::from("alice")).unwrap().contains("shares=301.302"));
+        pph.commit(passwordfile.clone()).unwrap();
+
+        let contents = std::fs::read_to_string(&passwordfile).unwrap();
+        assert!(contents.contains(r#""field":"gf65536""#));
+
+        // the field is recorded in the password file, and must match the one requested
+        assert!(PolyPasswordHasher::new(2, Some(passwordfile.clone()), None).is_err());
+
+        let mut pph = PolyPasswordHasherBuilder::new(2)
+            .field::<u16>()
+            .passwordfile(passwordfile)
+            .build()
+            .unwrap();
+        let logindata = vec![(String::from("alice"), String::from("puppy"))];
+        pph.unlock_database(logindata).unwrap();
+        assert!(pph.is_valid_login(String::from("admin"), String::from("kitten")).unwrap());
+        pph.create_account(String::from("bob"), String::from("hunter2"), 1).unwrap();
+        assert!(pph.export_account(String::from("bob")).unwrap().contains("shares=303"));
+    }
+
+    #[test]
+    fn test_verifiable_unlock() {
+        let passwordfile = env::temp_dir().join("test_verifiable_unlock.json");
+        let passwordfile = passwordfile.to_str().unwrap().to_string();
+
+        let builder = PolyPasswordHasherBuilder::new(2).vss(VssMode::Feldman);
+        assert!(matches!(builder.build().err().unwrap().kind, PPHErrorKind::ConfigError));
+
+        let mut pph = PolyPasswordHasherBuilder::new(2)
+            .field::<Scalar>()
+            .vss(VssMode::Feldman)
+            .hasher(Box::new(Sha256Hasher))
+            .build()
+            .unwrap();
+        pph.create_account(String::from("admin"), String::from("correct horse"), 1).unwrap();
+        pph.create_account(String::from("root"), String::from("battery staple"), 1).unwrap();
+        pph.create_account(String::from("alice"), String::from("kitten"), 1).unwrap();
+        pph.commit(passwordfile.clone()).unwrap();
+
+        let contents = std::fs::read_to_string(&passwordfile).unwrap();
+        assert!(contents.contains(r#""mode":"feldman""#));
+
+        // with three shares and a threshold of two, a mistyped password cannot be corrected for
+        // by decoding, but its share does not match the commitments and is set aside
+        let mut pph = PolyPasswordHasherBuilder::new(2)
+            .field::<Scalar>()
+            .passwordfile(passwordfile)
+            .build()
+            .unwrap();
+        let logindata = vec![
+            (String::from("admin"), String::from("correct horse")),
+            (String::from("root"), String::from("battery stapler")),
+            (String::from("alice"), String::from("kitten")),
+        ];
+        let report = pph.unlock_database(logindata).unwrap();
+        assert_eq!(report.invalidshares, vec![String::from("root")]);
+        assert!(pph.is_valid_login(String::from("root"), String::from("battery staple")).unwrap());
+    }
+
+    #[test]
+    fn test_pedersen_unlock() {
+        let passwordfile = env::temp_dir().join("test_pedersen_unlock.json");
+        let passwordfile = passwordfile.to_str().unwrap().to_string();
+
+        let mut pph = PolyPasswordHasherBuilder::new(2)
+            .field::<Scalar>()
+            .vss(VssMode::Pedersen)
+            .hasher(Box::new(Sha256Hasher))
+            .build()
+            .unwrap();
+        pph.create_account(String::from("admin"), String::from("correct horse"), 1).unwrap();
+        pph.create_account(String::from("root"), String::from("battery staple"), 2).unwrap();
+        pph.create_account(String::from("guest"), String::from("guest"), 0).unwrap();
+        assert!(matches!(
+            pph.export_account(String::from("admin")).unwrap_err().kind,
+            PPHErrorKind::FormatError
+        ));
+        pph.commit(passwordfile.clone()).unwrap();
+
+        let contents = std::fs::read_to_string(&passwordfile).unwrap();
+        assert!(contents.contains(r#""mode":"pedersen""#));
+        assert!(contents.contains(r#""blinding""#));
+
+        let mut pph = PolyPasswordHasherBuilder::new(2)
+            .field::<Scalar>()
+            .vss(VssMode::Pedersen)
+            .passwordfile(passwordfile.clone())
+            .build()
+            .unwrap();
+        let logindata = vec![
+            (String::from("admin"), String::from("correct horse!")),
+            (String::from("root"), String::from("battery staple")),
+        ];
+        let report = pph.unlock_database(logindata).unwrap();
+        assert_eq!(report.invalidshares, vec![String::from("admin")]);
+        assert!(pph.is_valid_login(String::from("guest"), String::from("guest")).unwrap());
+
+        // accounts created after unlocking get blinding shares that verify on the next unlock
+        pph.create_account(String::from("alice"), String::from("kitten"), 1).unwrap();
+        pph.commit(passwordfile.clone()).unwrap();
+        let mut pph = PolyPasswordHasherBuilder::new(2)
+            .field::<Scalar>()
+            .passwordfile(passwordfile)
+            .build()
+            .unwrap();
+        let logindata = vec![
+            (String::from("admin"), String::from("correct horse")),
+            (String::from("alice"), String::from("kitten")),
+        ];
+        let report = pph.unlock_database(logindata).unwrap();
+        assert!(report.invalidshares.is_empty());
+    }
+
+    #[test]
+    fn test_refresh_shares() {
+        let passwordfile = env::temp_dir().join("test_refresh_shares.json");
+        let passwordfile = passwordfile.to_str().unwrap().to_string();
+
+        let mut pph = PolyPasswordHasher::new(2, None, None).unwrap();
+        pph.set_hasher(Box::new(Sha256Hasher));
+        pph.create_account(String::from("admin"), String::from("correct horse"), 1).unwrap();
+        pph.create_account(String::from("root"), String::from("battery staple"), 2).unwrap();
+        pph.create_account(String::from("guest"), String::from("guest"), 0).unwrap();
+        let admin = pph.export_account(String::from("admin")).unwrap();
+        let guest = pph.export_account(String::from("guest")).unwrap();
+
+        pph.refresh_shares().unwrap();
+        assert_ne!(pph.export_account(String::from("admin")).unwrap(), admin);
+        assert_eq!(pph.export_account(String::from("guest")).unwrap(), guest);
+        assert!(pph.is_valid_login(String::from("admin"), String::from("correct horse")).unwrap());
+        assert!(pph.is_valid_login(String::from("guest"), String::from("guest")).unwrap());
+        pph.commit(passwordfile.clone()).unwrap();
+
+        let mut pph = PolyPasswordHasher::new(2, Some(passwordfile.clone()), None).unwrap();
+        let err = pph.refresh_shares().unwrap_err();
+        assert!(matches!(err.kind, PPHErrorKind::AuthError));
+        let logindata = vec![
+            (String::from("admin"), String::from("correct horse")),
+            (String::from("root"), String::from("battery staple")),
+        ];
+        let report = pph.unlock_database(logindata).unwrap();
+        assert!(report.invalidshares.is_empty());
+
+        // blinding shares are refreshed along with the shares they are committed with
+        let mut pph = PolyPasswordHasherBuilder::new(2)
+            .field::<Scalar>()
+            .vss(VssMode::Pedersen)
+            .hasher(Box::new(Sha256Hasher))
+            .build()
+            .unwrap();
+        pph.create_account(String::from("admin"), String::from("correct horse"), 1).unwrap();
+        pph.create_account(String::from("root"), String::from("battery staple"), 1).unwrap();
+        pph.create_account(String::from("alice"), String::from("kitten"), 1).unwrap();
+        pph.refresh_shares().unwrap();
+        pph.commit(passwordfile.clone()).unwrap();
+
+        let mut pph = PolyPasswordHasherBuilder::new(2)
+            .field::<Scalar>()
+            .passwordfile(passwordfile)
+            .build()
+            .unwrap();
+        let logindata = vec![
+            (String::from("admin"), String::from("correct horse")),
+            (String::from("root"), String::from("battery staple")),
+            (String::from("alice"), String::from("kitten")),
+        ];
+        let report = pph.unlock_database(logindata).unwrap();
+        assert!(report.invalidshares.is_empty());
+    }
+
+    #[test]
+    fn test_resalt_after_refresh() {
+        let passwordfile = env::temp_dir().join("test_resalt_after_refresh.json");
+        let passwordfile = passwordfile.to_str().unwrap().to_string();
+        let read_accounts = |passwordfile: &str| -> serde_json::Value {
+            let contents = std::fs::read_to_string(passwordfile).unwrap();
+            serde_json::from_str::<serde_json::Value>(&contents).unwrap()["accounts"].clone()
+        };
+
+        let mut pph = PolyPasswordHasher::new(2, None, None).unwrap();
+        pph.set_hasher(Box::new(Sha256Hasher));
+        pph.create_account(String::from("admin"), String::from("correct horse"), 1).unwrap();
+        pph.create_account(String::from("root"), String::from("battery staple"), 1).unwrap();
+        pph.create_account(String::from("guest"), String::from("guest"), 0).unwrap();
+        pph.commit(passwordfile.clone()).unwrap();
+        let before = read_accounts(&passwordfile);
+
+        // refreshed entries keep their salt until the account logs in again
+        pph.refresh_shares().unwrap();
+        pph.commit(passwordfile.clone()).unwrap();
+        let refreshed = read_accounts(&passwordfile);
+        for username in ["admin", "root"] {
+            assert_eq!(refreshed[username]["salt"], before[username]["salt"]);
+            assert_ne!(refreshed[username]["entries"], before[username]["entries"]);
+            assert_eq!(refreshed[username]["refreshed"], true);
+        }
+        assert_eq!(refreshed["guest"], before["guest"]);
+
+        let mut pph = PolyPasswordHasher::new(2, Some(passwordfile.clone()), None).unwrap();
+        let logindata = vec![
+            (String::from("admin"), String::from("correct horse")),
+            (String::from("root"), String::from("battery staple")),
+        ];
+        pph.unlock_database(logindata.clone()).unwrap();
+        assert!(pph.is_valid_login(String::from("admin"), String::from("correct horse")).unwrap());
+        pph.commit(passwordfile.clone()).unwrap();
+        let loggedin = read_accounts(&passwordfile);
+        assert_ne!(loggedin["admin"]["salt"], before["admin"]["salt"]);
+        assert!(loggedin["admin"].get("refreshed").is_none());
+        assert_eq!(loggedin["root"], refreshed["root"]);
+
+        let mut pph = PolyPasswordHasher::new(2, Some(passwordfile), None).unwrap();
+        let report = pph.unlock_database(logindata).unwrap();
+        assert!(report.invalidshares.is_empty());
+    }
+
+    #[test]
+    fn test_set_threshold() {
+        let passwordfile = env::temp_dir().join("test_set_threshold.json");
+        let passwordfile = passwordfile.to_str().unwrap().to_string();
+
+        let mut pph = PolyPasswordHasher::new(2, None, None).unwrap();
+        pph.set_hasher(Box::new(Sha256Hasher));
+        pph.create_account(String::from("admin"), String::from("correct horse"), 1).unwrap();
+        pph.create_account(String::from("root"), String::from("battery staple"), 1).unwrap();
+        pph.create_account(String::from("alice"), String::from("kitten"), 1).unwrap();
+        pph.create_account(String::from("guest"), String::from("guest"), 0).unwrap();
+
+        let err = pph.set_threshold(4).unwrap_err();
+        assert!(matches!(err.kind, PPHErrorKind::ShardError));
+        assert!(pph.set_threshold(0).is_err());
+        pph.set_threshold(3).unwrap();
+        assert!(pph.is_valid_login(String::from("admin"), String::from("correct horse")).unwrap());
+        assert!(pph.is_valid_login(String::from("guest"), String::from("guest")).unwrap());
+
+        // removing a share holder would now make the threshold unreachable
+        let err = pph.remove_account(String::from("alice")).unwrap_err();
+        assert!(matches!(err.kind, PPHErrorKind::ShardError));
+        pph.commit(passwordfile.clone()).unwrap();
+
+        // the password file is loaded with the threshold it records, whichever one is given
+        let mut pph = PolyPasswordHasher::new(2, Some(passwordfile.clone()), None).unwrap();
+        let err = pph.set_threshold(2).unwrap_err();
+        assert!(matches!(err.kind, PPHErrorKind::AuthError));
+        let logindata = vec![
+            (String::from("admin"), String::from("correct horse")),
+            (String::from("root"), String::from("battery staple")),
+        ];
+        let err = pph.unlock_database(logindata).unwrap_err();
+        assert!(matches!(err.kind, PPHErrorKind::RecoveryError));
+
+        let logindata = vec![
+            (String::from("admin"), String::from("correct horse")),
+            (String::from("root"), String::from("battery staple")),
+            (String::from("alice"), String::from("kitten")),
+        ];
+        pph.unlock_database(logindata).unwrap();
+
+        // lowering the threshold lets a single share holder unlock the database
+        pph.set_threshold(1).unwrap();
+        pph.commit(passwordfile.clone()).unwrap();
+        let mut pph = PolyPasswordHasher::new(3, Some(passwordfile), None).unwrap();
+        let logindata = vec![(String::from("root"), String::from("battery staple"))];
+        pph.unlock_database(logindata).unwrap();
+        assert!(pph.is_valid_login(String::from("alice"), String::from("kitten")).unwrap());
+    }
+
+    #[test]
+    fn test_failed_reshare() {
+        let passwordfile = env::temp_dir().join("test_failed_reshare.json");
+        let passwordfile = passwordfile.to_str().unwrap().to_string();
+
+        let mut pph = PolyPasswordHasher::new(2, None, None).unwrap();
+        pph.set_hasher(Box::new(Sha256Hasher));
+        pph.create_account(String::from("admin"), String::from("correct horse"), 1).unwrap();
+        pph.create_account(String::from("root"), String::from("battery staple"), 1).unwrap();
+        pph.create_account(String::from("alice"), String::from("kitten"), 1).unwrap();
+        pph.commit(passwordfile.clone()).unwrap();
+
+        // an entry with a share number outside of GF(2^8) cannot be re-protected
+        let contents = std::fs::read_to_string(&passwordfile).unwrap();
+        let contents = contents.replace(r#""sharenumber":3"#, r#""sharenumber":300"#);
+        std::fs::write(&passwordfile, contents).unwrap();
+
+        let mut pph = PolyPasswordHasher::new(2, Some(passwordfile.clone()), None).unwrap();
+        let logindata = vec![
+            (String::from("admin"), String::from("correct horse")),
+            (String::from("root"), String::from("battery staple")),
+        ];
+        pph.unlock_database(logindata.clone()).unwrap();
+        assert!(pph.refresh_shares().is_err());
+        assert!(pph.set_threshold(3).is_err());
+
+        // the secret, threshold and entries are left as they were
+        assert!(pph.is_valid_login(String::from("admin"), String::from("correct horse")).unwrap());
+        assert!(pph.is_valid_login(String::from("root"), String::from("battery staple")).unwrap());
+        pph.commit(passwordfile.clone()).unwrap();
+        let mut pph = PolyPasswordHasher::new(2, Some(passwordfile), None).unwrap();
+        let report = pph.unlock_database(logindata).unwrap();
+        assert!(report.invalidshares.is_empty());
     }
 }
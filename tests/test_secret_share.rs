@@ -8,55 +8,280 @@ extern crate polypasswordhasher;
 #[cfg(test)]
 mod tests {
 
-    use polypasswordhasher::math::polynomial;
+    use polypasswordhasher::error::PPHErrorKind;
+    use polypasswordhasher::math::{polynomial, Field};
+    use polypasswordhasher::ristretto::Scalar;
     use polypasswordhasher::secretshare::ShamirSecret;
+    use polypasswordhasher::vss::Commitments;
+
+    use std::convert::TryFrom;
 
     // TODO: migrate to math tests
     #[test]
     fn test_full_lagrange() {
         assert_eq!(
-            polynomial::full_lagrange(vec![2, 4, 5], vec![14, 30, 32]),
+            polynomial::full_lagrange(vec![2u8, 4, 5], vec![14, 30, 32]).unwrap(),
             vec![43, 168, 150]
         );
     }
 
+    #[test]
+    fn test_berlekamp_welch() {
+        // f(x) = 43 + 168x + 150x^2, as above, with f(3) replaced by an invalid value
+        let xs: Vec<u8> = vec![1, 2, 3, 4, 5];
+        let mut fxs: Vec<u8> = xs
+            .iter()
+            .map(|x| polynomial::compute_polynomial(*x, vec![43, 168, 150]).unwrap())
+            .collect();
+        let decoded = polynomial::berlekamp_welch(xs.clone(), fxs.clone(), 3);
+        assert_eq!(decoded.unwrap(), vec![43, 168, 150]);
+
+        fxs[2] ^= 0x5a;
+        let decoded = polynomial::berlekamp_welch(xs.clone(), fxs.clone(), 3);
+        assert_eq!(decoded.unwrap(), vec![43, 168, 150]);
+
+        fxs[3] ^= 0x5a;
+        assert_ne!(polynomial::berlekamp_welch(xs, fxs, 3).ok(), Some(vec![43, 168, 150]));
+    }
+
     #[test]
     fn test_generate_secret() {
         let message = String::from("Secret message");
-        let _ = ShamirSecret::new(5, Some(message));
+        let _ = ShamirSecret::new(5, Some(message.into_bytes())).unwrap();
     }
 
     #[test]
     fn test_recover_secret() {
-        let secret = ShamirSecret::new(3, Some("Hello".to_string()));
+        let secret = ShamirSecret::new(3, Some("Hello".to_string().into_bytes())).unwrap();
 
         // Calculate 3 new shares, since k = 3
-        let a = secret.compute_share(1);
-        let b = secret.compute_share(2);
-        let c = secret.compute_share(3);
+        let a = secret.compute_share(1).unwrap();
+        let b = secret.compute_share(2).unwrap();
+        let c = secret.compute_share(3).unwrap();
 
         // Notice how first element is always x
         println!("A: {:?}\nB: {:?}\nC: {:?}", a, b, c);
 
         // New ShamirSecret object with no secret.
-        let mut newsecret = ShamirSecret::new(3, None);
+        let mut newsecret = ShamirSecret::new(3, None).unwrap();
 
         // Recover with vector of 3 shares. This sets secret for new
         // ShamirSecret object
-        let _ = newsecret.recover_secretdata(vec![a, b, c]);
+        newsecret.recover_secretdata(vec![a, b, c]).unwrap();
 
         // Check!
         assert_eq!(newsecret.secretdata, secret.secretdata);
         println!("{:?} == {:?}", newsecret.secretdata, secret.secretdata);
     }
 
+    #[test]
+    fn test_refresh_secret() {
+        let mut secret = ShamirSecret::new(3, Some("Hello".to_string().into_bytes())).unwrap();
+        let oldshares: Vec<Vec<u8>> = (1..=3).map(|x| secret.compute_share(x).unwrap()).collect();
+
+        // every element gets its own polynomial
+        assert_ne!(secret.coefficients[0][1..], secret.coefficients[1][1..]);
+
+        secret.refresh().unwrap();
+        let newshares: Vec<Vec<u8>> = (1..=3).map(|x| secret.compute_share(x).unwrap()).collect();
+        assert_ne!(oldshares, newshares);
+
+        let mut newsecret = ShamirSecret::new(3, None).unwrap();
+        newsecret.recover_secretdata(newshares.clone()).unwrap();
+        assert_eq!(newsecret.secretdata, secret.secretdata);
+
+        // shares from before the refresh do not combine with new ones
+        let mixed = vec![oldshares[0].clone(), newshares[1].clone(), newshares[2].clone()];
+        let mut newsecret = ShamirSecret::new(3, None).unwrap();
+        newsecret.recover_secretdata(mixed).unwrap();
+        assert_ne!(newsecret.secretdata, secret.secretdata);
+
+        assert!(ShamirSecret::new(3, None).unwrap().refresh().is_err());
+
+        // resharing at a lower degree needs fewer shares to recover the same secret
+        secret.reshare(2).unwrap();
+        assert!(secret.reshare(0).is_err());
+        assert_eq!(secret.coefficients[0].len(), 2);
+        let shares: Vec<Vec<u8>> = (4..=5).map(|x| secret.compute_share(x).unwrap()).collect();
+        let mut newsecret = ShamirSecret::new(2, None).unwrap();
+        newsecret.recover_secretdata(shares).unwrap();
+        assert_eq!(newsecret.secretdata, secret.secretdata);
+    }
+
     #[test]
     fn test_valid_share() {
         let message = String::from("Secret message");
 
-        let s = ShamirSecret::new(2, Some(message));
-        let a = s.compute_share(1);
+        let s = ShamirSecret::new(2, Some(message.into_bytes())).unwrap();
+        let a = s.compute_share(1).unwrap();
+
+        assert!(s.is_valid_share(a).unwrap());
+    }
+
+    #[test]
+    fn test_recover_secret_with_errors() {
+        let secret = ShamirSecret::new(2, Some("Hello".to_string().into_bytes())).unwrap();
+
+        // Calculate 4 new shares and corrupt one of them
+        let a = secret.compute_share(1).unwrap();
+        let b = secret.compute_share(2).unwrap();
+        let mut c = secret.compute_share(3).unwrap();
+        let d = secret.compute_share(4).unwrap();
+        c[1] ^= 0xff;
+
+        let mut newsecret = ShamirSecret::new(2, None).unwrap();
+        let invalid = newsecret.recover_secretdata_with_errors(vec![a, b, c, d]).unwrap();
+
+        assert_eq!(invalid, vec![3]);
+        assert_eq!(newsecret.secretdata, secret.secretdata);
+    }
+
+    #[test]
+    fn test_recover_scalar_with_errors() {
+        // share numbers are the scalars that fit in 16 bits
+        assert_eq!(u16::try_from(Scalar::from(300)).unwrap(), 300);
+        assert!(u16::try_from(Scalar::from(300).mul(Scalar::from(u16::MAX))).is_err());
+
+        // decoding over a prime field, where negation is not the identity
+        let secretdata: Vec<u8> = (0..2).flat_map(|_| Scalar::random().0.to_vec()).collect();
+        let secret: ShamirSecret<Scalar> = ShamirSecret::with_field(2, Some(secretdata)).unwrap();
+
+        let mut shares: Vec<Vec<u8>> =
+            (1..=4).map(|x| secret.compute_share(Scalar::from(x)).unwrap()).collect();
+        shares[2][40] ^= 0x01;
+
+        let mut newsecret = ShamirSecret::<Scalar>::with_field(2, None).unwrap();
+        let invalid = newsecret.recover_secretdata_with_errors(shares).unwrap();
+
+        assert_eq!(invalid, vec![Scalar::from(3)]);
+        assert_eq!(newsecret.secretdata, secret.secretdata);
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert!(matches!(ShamirSecret::new(0, None).unwrap_err().kind, PPHErrorKind::ShareError));
+
+        let secret = ShamirSecret::new(3, Some("Hello".to_string().into_bytes())).unwrap();
+        let err = secret.compute_share(0).unwrap_err();
+        assert!(matches!(err.kind, PPHErrorKind::ShareError));
+
+        // too few shares, and distinct shares with the same x
+        let a = secret.compute_share(1).unwrap();
+        let b = secret.compute_share(2).unwrap();
+        let mut c = a.clone();
+        c[1] ^= 0xff;
+
+        let mut newsecret = ShamirSecret::new(3, None).unwrap();
+        let err = newsecret.recover_secretdata(vec![a.clone(), b.clone()]).unwrap_err();
+        assert!(matches!(err.kind, PPHErrorKind::ShareError));
+        let err = newsecret.recover_secretdata(vec![a, b, c]).unwrap_err();
+        assert!(matches!(err.kind, PPHErrorKind::ShareError));
+
+        let err = polynomial::full_lagrange(vec![1u8, 2], vec![3]).unwrap_err();
+        assert!(matches!(err.kind, PPHErrorKind::FieldError));
+    }
+
+    #[test]
+    fn test_gf65536() {
+        // x generates every nonzero element, and division undoes multiplication
+        let mut x: u16 = 1;
+        for i in 1..65536u32 {
+            x = x.mul(2);
+            assert!(x != 1 || i == 65535);
+        }
+        assert_eq!(x, 1);
+        for (a, b) in [(0x1234u16, 0xbeefu16), (1, 0xffff), (0x8000, 0x8000)] {
+            assert_eq!(a.mul(b).div(b).unwrap(), a);
+        }
+        assert!(7u16.div(0).is_err());
+
+        let secret: ShamirSecret<u16> =
+            ShamirSecret::with_field(3, Some("Hello!".to_string().into_bytes())).unwrap();
+        assert!(ShamirSecret::<u16>::with_field(3, Some(vec![1, 2, 3])).is_err());
+
+        // share numbers beyond 255 take two bytes
+        let shares: Vec<Vec<u8>> =
+            [1u16, 300, 4096, 65535].iter().map(|x| secret.compute_share(*x).unwrap()).collect();
+        assert_eq!(shares[1][..2], [0x01, 0x2c]);
+        assert_eq!(shares[1].len(), 8);
+
+        let mut newsecret = ShamirSecret::<u16>::with_field(3, None).unwrap();
+        newsecret.recover_secretdata(shares[..3].to_vec()).unwrap();
+        assert_eq!(newsecret.secretdata, secret.secretdata);
+
+        let mut invalidshares = shares.clone();
+        invalidshares.push(secret.compute_share(5000).unwrap());
+        invalidshares[2][3] ^= 0xff;
+        let mut newsecret = ShamirSecret::<u16>::with_field(3, None).unwrap();
+        let invalid = newsecret.recover_secretdata_with_errors(invalidshares).unwrap();
+        assert_eq!(invalid, vec![4096]);
+        assert_eq!(newsecret.secretdata, secret.secretdata);
+    }
+
+    #[test]
+    fn test_feldman_vss() {
+        let secretdata: Vec<u8> = (0..2).flat_map(|_| Scalar::random().0.to_vec()).collect();
+        let secret: ShamirSecret<Scalar> = ShamirSecret::with_field(2, Some(secretdata)).unwrap();
+        let commitments = Commitments::feldman(&secret).unwrap();
+        assert!(Commitments::feldman(&ShamirSecret::new(2, Some(vec![1, 2])).unwrap()).is_err());
+
+        let shares: Vec<Vec<u8>> =
+            (1..=4).map(|x| secret.compute_share(Scalar::from(x)).unwrap()).collect();
+        for share in shares.iter() {
+            assert!(commitments.verify_share(share, None));
+        }
+
+        // forged and malformed shares do not match the commitments
+        let mut forged = shares.clone();
+        forged[1][40] ^= 0x01;
+        forged[3][0] = 5;
+        assert!(!commitments.verify_share(&forged[1], None));
+        assert!(!commitments.verify_share(&forged[3], None));
+        assert!(!commitments.verify_share(&shares[0][..64], None));
+
+        // two forged shares out of four are more than Berlekamp-Welch can correct for, but they
+        // are set aside before recovering
+        let mut newsecret = ShamirSecret::<Scalar>::with_field(2, None).unwrap();
+        let invalid =
+            newsecret.recover_secretdata_verified(forged.clone(), None, &commitments).unwrap();
+        assert_eq!(invalid, vec![Scalar::from(2), Scalar::from(5)]);
+        assert_eq!(newsecret.secretdata, secret.secretdata);
 
-        assert_eq!(s.is_valid_share(a), true);
+        let mut newsecret = ShamirSecret::<Scalar>::with_field(3, None).unwrap();
+        let err = newsecret.recover_secretdata_verified(forged, None, &commitments).unwrap_err();
+        assert!(matches!(err.kind, PPHErrorKind::RecoveryError));
+    }
+
+    #[test]
+    fn test_pedersen_vss() {
+        let secretdata: Vec<u8> = Scalar::random().0.to_vec();
+        let secret: ShamirSecret<Scalar> = ShamirSecret::with_field(3, Some(secretdata)).unwrap();
+        let blinding = secret.blinding().unwrap();
+        let commitments = Commitments::pedersen(&secret, &blinding).unwrap();
+        assert_ne!(blinding.secretdata, secret.secretdata);
+
+        // the commitments differ from Feldman ones to the same secret, which reveal its multiple
+        // of the generator
+        assert_ne!(commitments, Commitments::feldman(&secret).unwrap());
+
+        let shares: Vec<Vec<u8>> =
+            (1..=5).map(|x| secret.compute_share(Scalar::from(x)).unwrap()).collect();
+        let blindingshares: Vec<Vec<u8>> =
+            (1..=5).map(|x| blinding.compute_share(Scalar::from(x)).unwrap()).collect();
+        for (share, blindingshare) in shares.iter().zip(blindingshares.iter()) {
+            assert!(commitments.verify_share(share, Some(blindingshare)));
+            assert!(!commitments.verify_share(share, None));
+        }
+        assert!(!commitments.verify_share(&shares[0], Some(&blindingshares[1])));
+
+        let mut forged = blindingshares.clone();
+        forged[4][33] ^= 0x80;
+        assert!(!commitments.verify_share(&shares[4], Some(&forged[4])));
+
+        let mut newsecret = ShamirSecret::<Scalar>::with_field(3, None).unwrap();
+        let invalid =
+            newsecret.recover_secretdata_verified(shares, Some(forged), &commitments).unwrap();
+        assert_eq!(invalid, vec![Scalar::from(5)]);
+        assert_eq!(newsecret.secretdata, secret.secretdata);
     }
 }